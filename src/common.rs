@@ -1,16 +1,20 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::fmt::{Display, Formatter};
 use clap::{ArgEnum};
-use serde::{Serialize, Deserialize};
-use anyhow::{Result, Error};
+use schemars::{gen::SchemaGenerator, schema::{InstanceType, Metadata, Schema, SchemaObject}, JsonSchema};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use anyhow::{anyhow, Result, Error};
 
 
-#[derive(ArgEnum, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(ArgEnum, Debug, Default, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
 pub enum LogLevel {
     #[clap(name = "debug")]
     Debug = 4,
     #[clap(name = "info")]
+    #[default]
     Info = 3,
     #[clap(name = "warn")]
     Warn = 2,
@@ -32,13 +36,218 @@ impl FromStr for LogLevel {
     }
 }
 
-impl Default for LogLevel {
-    fn default() -> Self {
-        LogLevel::Info
+/// A duration which can be written either as a number of seconds or as a human readable
+/// string composed of `<number><unit>` parts (e.g. `90`, `1m30s`, `500ms`, `2h`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HumanDuration(pub std::time::Duration);
+
+impl HumanDuration {
+    pub fn from_secs(secs: u64) -> Self {
+        Self(std::time::Duration::from_secs(secs))
+    }
+
+    pub fn as_duration(&self) -> std::time::Duration {
+        self.0
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        if let Ok(secs) = text.parse::<f64>() {
+            if secs < 0.0 || !secs.is_finite() {
+                return Err(anyhow!("Invalid duration: {}", s));
+            }
+            return Ok(Self(std::time::Duration::from_secs_f64(secs)));
+        }
+
+        let mut total = std::time::Duration::ZERO;
+        let mut rest = text;
+        while !rest.is_empty() {
+            let number_len = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .ok_or_else(|| anyhow!("Invalid duration: {}, missing unit", s))?;
+            let unit_len = rest[number_len..].find(|c: char| c.is_ascii_digit() || c == '.')
+                .unwrap_or(rest.len() - number_len);
+            let value: f64 = rest[..number_len].parse()
+                .map_err(|_| anyhow!("Invalid duration: {}", s))?;
+            let multiplier = match &rest[number_len..number_len + unit_len] {
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                "d" => 86400.0,
+                unit => return Err(anyhow!("Invalid duration: {}, unknown unit \"{}\"", s, unit)),
+            };
+            total += std::time::Duration::from_secs_f64(value * multiplier);
+            rest = &rest[number_len + unit_len..];
+        }
+
+        if text.is_empty() {
+            Err(anyhow!("Invalid duration: empty value"))
+        } else {
+            Ok(Self(total))
+        }
+    }
+}
+
+impl Display for HumanDuration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.subsec_millis() > 0 {
+            write!(f, "{}ms", self.0.as_millis())
+        } else {
+            write!(f, "{}s", self.0.as_secs())
+        }
+    }
+}
+
+impl From<HumanDuration> for std::time::Duration {
+    fn from(duration: HumanDuration) -> Self {
+        duration.0
+    }
+}
+
+impl Serialize for HumanDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(f64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(secs) => HumanDuration::from_str(&secs.to_string()),
+            Raw::Text(text) => HumanDuration::from_str(&text),
+        }.map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for HumanDuration {
+    fn schema_name() -> String {
+        "HumanDuration".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(vec![InstanceType::Number, InstanceType::String].into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some("Number of seconds or a duration such as `500ms`, `1m30s` or `2h`.".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }.into()
+    }
+}
+
+/// A size in bytes which can be written either as a number of bytes or as a human readable
+/// string with a binary unit (e.g. `1024`, `500KB`, `1.5GB`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    pub fn as_bytes(&self) -> u64 {
+        self.0
+    }
+}
+
+const BYTE_UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+impl FromStr for ByteSize {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let number_len = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+        let value: f64 = text[..number_len].parse()
+            .map_err(|_| anyhow!("Invalid size: {}", s))?;
+        let unit = text[number_len..].trim().to_uppercase();
+        let exponent = match unit.as_str() {
+            "" => 0,
+            unit => BYTE_UNITS.iter().position(|u| *u == unit || u[..1] == *unit)
+                .ok_or_else(|| anyhow!("Invalid size: {}, unknown unit \"{}\"", s, unit))?,
+        };
+        Ok(Self((value * 1024f64.powi(exponent as i32)) as u64))
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let exponent = (0..BYTE_UNITS.len()).rev()
+            .find(|exponent| self.0 > 0 && self.0.is_multiple_of(1024u64.pow(*exponent as u32)))
+            .unwrap_or(0);
+        write!(f, "{}{}", self.0 / 1024u64.pow(exponent as u32), BYTE_UNITS[exponent])
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(bytes) => Ok(ByteSize(bytes)),
+            Raw::Text(text) => ByteSize::from_str(&text).map_err(serde::de::Error::custom),
+        }
     }
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+impl JsonSchema for ByteSize {
+    fn schema_name() -> String {
+        "ByteSize".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(vec![InstanceType::Integer, InstanceType::String].into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some("Number of bytes or a size such as `500KB`, `100MB` or `1.5GB`.".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }.into()
+    }
+}
+
+/// Error caused by the value at the given path within the job file (e.g. `tasks.2.needs`)
+#[derive(Debug)]
+pub struct LocatedError {
+    pub path: String,
+    pub message: String,
+}
+
+impl LocatedError {
+    pub fn at(path: &str, message: impl Display) -> Error {
+        Error::new(LocatedError { path: path.to_string(), message: message.to_string() })
+    }
+}
+
+impl Display for LocatedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for LocatedError {}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Env {
     #[serde(flatten)]
     base: HashMap<String, String>,
@@ -49,16 +258,28 @@ impl Env {
         Self { base: std::env::vars().collect() }
     }
 
+    /// Loads the variables of a dotenv file. The lines which can not be parsed are returned as errors
+    /// naming the line number (without its contents, since the file may hold secrets).
     pub fn from_path(path: &PathBuf) -> Result<(Self, Vec<Error>)> {
         let mut base = HashMap::new();
         let mut errors = Vec::new();
 
+        // An item is parsed from every line which is neither blank nor a comment
+        let contents = std::fs::read_to_string(path)?;
+        let mut line_numbers = contents.lines().enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(i, _)| i + 1);
+
         #[allow(deprecated)]
         for item in dotenv::from_path_iter(path)? {
+            let line_no = line_numbers.next().unwrap_or_default();
             match item {
                 Ok((k, v)) => {
                     base.insert(k, v);
                 },
+                Err(dotenv::Error::LineParse(..)) => {
+                    errors.push(anyhow!("Invalid line {}", line_no));
+                }
                 Err(e) => {
                     errors.push(e.into());
                 }
@@ -72,10 +293,21 @@ impl Env {
         self.base.insert(k, v)
     }
 
-    #[allow(dead_code)]
     pub fn get(&self, k: &str) -> Option<&String> {
         self.base.get(k)
     }
+
+    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, String, String> {
+        self.base.iter()
+    }
+
+    /// Returns the variables which are new or changed compared to the other env
+    pub fn difference(&self, other: &Env) -> Env {
+        self.base.iter()
+            .filter(|(k, v)| other.base.get(*k) != Some(*v))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
 }
 
 impl From<HashMap<String, String>> for Env {
@@ -104,3 +336,65 @@ impl IntoIterator for Env {
         self.base.into_iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+    use test_case::test_case;
+    use crate::common::{ByteSize, Env, HumanDuration};
+
+    #[test_case("90", Duration::from_secs(90))]
+    #[test_case("1.5", Duration::from_millis(1500))]
+    #[test_case("500ms", Duration::from_millis(500))]
+    #[test_case("1m30s", Duration::from_secs(90))]
+    #[test_case("2h", Duration::from_secs(7200))]
+    #[test_case("1d", Duration::from_secs(86400))]
+    fn test_parse_duration(text: &str, expected: Duration) {
+        assert_eq!(HumanDuration::from_str(text).unwrap().as_duration(), expected);
+    }
+
+    #[test_case(" " ; "empty")]
+    #[test_case("-1")]
+    #[test_case("10x")]
+    #[test_case("m")]
+    fn test_parse_duration_invalid(text: &str) {
+        assert!(HumanDuration::from_str(text).is_err());
+    }
+
+    #[test_case("1024", 1024)]
+    #[test_case("500KB", 500 * 1024)]
+    #[test_case("1.5 GB", 3 * 512 * 1024 * 1024)]
+    #[test_case("100m", 100 * 1024 * 1024)]
+    fn test_parse_size(text: &str, expected: u64) {
+        assert_eq!(ByteSize::from_str(text).unwrap().as_bytes(), expected);
+    }
+
+    #[test_case(0, "0B")]
+    #[test_case(1000, "1000B")]
+    #[test_case(1024, "1KB")]
+    #[test_case(3 * 1024 * 1024 * 1024, "3GB")]
+    fn test_display_size(bytes: u64, expected: &str) {
+        assert_eq!(ByteSize(bytes).to_string(), expected);
+    }
+
+    #[test_case("" ; "empty")]
+    #[test_case("MB" ; "missing number")]
+    #[test_case("10XB" ; "unknown unit")]
+    fn test_parse_size_invalid(text: &str) {
+        assert!(ByteSize::from_str(text).is_err());
+    }
+
+    #[test]
+    fn test_env_invalid_lines() {
+        let path = std::env::temp_dir().join(format!("nauman-env-{}.env", std::process::id()));
+        std::fs::write(&path, "# Secrets\nUSER=admin\nPASSWORD hunter2\n\nTOKEN=abc\nKEY='unterminated\n").unwrap();
+        let (env, errors) = Env::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(env.get("USER").map(String::as_str), Some("admin"));
+        assert_eq!(env.get("TOKEN").map(String::as_str), Some("abc"));
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors, vec!["Invalid line 3", "Invalid line 6"]);
+    }
+}
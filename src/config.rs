@@ -1,19 +1,27 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::{Display, Formatter},
 };
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use heck::SnakeCase;
 use lazy_static::lazy_static;
-use serde::{Serialize, Deserialize};
+use schemars::{
+    gen::SchemaGenerator, schema_for,
+    schema::{InstanceType, ObjectValidation, RootSchema, Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
 use anyhow::{anyhow, Context as AnyhowContext, Result};
 use regex::Regex;
 use crate::{
-    common::Env,
+    common::{ByteSize, Env, HumanDuration},
 };
 use crate::common::LogLevel;
+use crate::utils::job_id_from_path;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Options {
     /// The default shell which is used to run commands.
     #[serde(default)]
@@ -39,6 +47,25 @@ pub struct Options {
     #[serde(default = "temp_path_default")]
     /// Path to a folder to store temporary files in
     pub temp_path: PathBuf,
+    /// Default timeout for every task in the job.
+    pub timeout: Option<HumanDuration>,
+    /// Time given to a timed out task to exit after SIGTERM before it is killed with SIGKILL.
+    #[serde(default = "kill_grace_period_default")]
+    pub kill_grace_period: HumanDuration,
+    /// Maximum number of tasks which are executed in parallel.
+    #[serde(default = "max_parallel_default")]
+    pub max_parallel: usize,
+    /// Determines the exit code of nauman after the job is executed.
+    #[serde(default)]
+    pub exit_code: ExitCodePolicy,
+    /// Whether failing hooks should fail the job.
+    #[serde(default = "false_default")]
+    pub fail_on_hook_failure: bool,
+    /// Retention policy for the log directories of the previous runs.
+    pub retention: Option<Retention>,
+    /// Run summaries to write after the job is executed.
+    #[serde(default)]
+    pub summary: Vec<Summary>,
 }
 
 impl Default for Options {
@@ -53,16 +80,195 @@ impl Default for Options {
             system_env: true_default(),
             dotenv: None,
             temp_path: temp_path_default(),
+            timeout: None,
+            kill_grace_period: kill_grace_period_default(),
+            max_parallel: max_parallel_default(),
+            exit_code: ExitCodePolicy::default(),
+            fail_on_hook_failure: false_default(),
+            retention: None,
+            summary: Vec::new(),
         }
     }
 }
 
+/// Options whose defaults differ between machines, which are left out of the schema
+const MACHINE_DEPENDENT_OPTIONS: &[&str] = &["shell", "temp_path"];
+
 fn temp_path_default() -> PathBuf {
     std::env::temp_dir()
 }
 
+/// Retention policy for the log directories of the previous runs of a job
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Retention {
+    /// Maximum number of runs to keep.
+    pub keep_last: Option<usize>,
+    /// Maximum age of the runs to keep.
+    pub max_age: Option<HumanDuration>,
+    /// Maximum total size of the runs to keep.
+    pub max_size: Option<ByteSize>,
+    /// Compress the runs older than the given age instead of keeping them as directories.
+    pub compress_after: Option<HumanDuration>,
+}
+
+/// Format of a run summary
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryFormat {
+    /// JSON document with the results of all the commands.
+    Json,
+    /// JUnit XML report with a test case per task.
+    Junit,
+    /// Markdown table with the results of all the commands.
+    Markdown,
+}
+
+impl SummaryFormat {
+    /// Name of the summary file written to the run log directory by default
+    pub fn default_file(&self) -> &'static str {
+        match self {
+            SummaryFormat::Json => "summary.json",
+            SummaryFormat::Junit => "junit.xml",
+            SummaryFormat::Markdown => "summary.md",
+        }
+    }
+}
+
+impl FromStr for SummaryFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(SummaryFormat::Json),
+            "junit" => Ok(SummaryFormat::Junit),
+            "markdown" => Ok(SummaryFormat::Markdown),
+            _ => Err(anyhow!("Invalid summary format: {}. Expected json, junit or markdown", s)),
+        }
+    }
+}
+
+/// Run summary written after the job is executed
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct Summary {
+    /// Format of the summary.
+    pub format: SummaryFormat,
+    /// The file to write the summary to (relative to the run log directory).
+    pub output: Option<String>,
+}
+
+/// Parses a summary of form `<format>` or `<format>=<output>`
+impl FromStr for Summary {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, output) = match s.split_once('=') {
+            Some((format, output)) => (format, Some(output.to_string())),
+            None => (s, None),
+        };
+        Ok(Summary { format: format.parse()?, output })
+    }
+}
+
+fn kill_grace_period_default() -> HumanDuration {
+    HumanDuration::from_secs(10)
+}
+
+fn max_parallel_default() -> usize {
+    1
+}
+
+/// Policy determining the exit code of a finished job
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ExitCodePolicy {
+    /// Exit with the exit code of the first failed task.
+    #[default]
+    FirstFailure,
+    /// Exit with the exit code of the last executed task.
+    Last,
+    /// Always exit with a zero exit code.
+    AlwaysZero,
+    /// Exit with the given exit code if any of the tasks failed.
+    Fixed(i32),
+}
+
+impl FromStr for ExitCodePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first_failure" => Ok(ExitCodePolicy::FirstFailure),
+            "last" => Ok(ExitCodePolicy::Last),
+            "always_zero" => Ok(ExitCodePolicy::AlwaysZero),
+            _ => s.parse().map(ExitCodePolicy::Fixed).map_err(|_| anyhow!(
+                "Invalid exit code policy: {}. Expected first_failure, last, always_zero or a number", s
+            )),
+        }
+    }
+}
+
+impl Display for ExitCodePolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitCodePolicy::Fixed(code) => write!(f, "{}", code),
+            _ => write!(f, "{}", format!("{:?}", self).to_snake_case()),
+        }
+    }
+}
+
+impl Serialize for ExitCodePolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ExitCodePolicy::Fixed(code) => serializer.serialize_i32(*code),
+            _ => serializer.serialize_str(&self.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ExitCodePolicy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(i32),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(code) => Ok(ExitCodePolicy::Fixed(code)),
+            Raw::Text(text) => ExitCodePolicy::from_str(&text).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl JsonSchema for ExitCodePolicy {
+    fn schema_name() -> String {
+        "ExitCodePolicy".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        let names = ["first_failure", "last", "always_zero"];
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![
+                    SchemaObject {
+                        instance_type: Some(InstanceType::String.into()),
+                        enum_values: Some(names.iter().map(|name| (*name).into()).collect()),
+                        ..Default::default()
+                    }.into(),
+                    SchemaObject {
+                        instance_type: Some(InstanceType::Integer.into()),
+                        ..Default::default()
+                    }.into(),
+                ]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }.into()
+    }
+}
+
 /// Shell to run command with
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Eq, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum ShellType {
     Bash,
@@ -139,7 +345,7 @@ impl Default for ShellType {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Shell {
     /// The shell type.
     pub shell: Option<ShellType>,
@@ -155,52 +361,273 @@ impl Display for Shell {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Script {
+    /// Path to the script file (relative to the directory of the job file).
+    pub script: String,
+    /// Arguments passed to the script.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The shell type used to run the script. By default, the script's shebang is used.
+    pub shell: Option<ShellType>,
+    /// The shell which is used to run the script.
+    pub shell_path: Option<String>,
+}
+
+impl Script {
+    /// Whether the script is run with an explicitly configured shell (instead of its shebang)
+    pub fn has_shell(&self) -> bool {
+        self.shell.is_some() || self.shell_path.is_some()
+    }
+}
+
+impl Display for Script {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.script)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SubJob {
+    /// Path to the job file which is run as the task (relative to the directory of the job file).
+    pub job: String,
+}
+
+impl Display for SubJob {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.job)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Exec {
+    /// Program and its arguments which are executed directly (without a shell).
+    pub command: Vec<String>,
+    /// Whether the `${VAR}` references to environment variables within the arguments are expanded.
+    #[serde(default = "true_default")]
+    pub expand_env: bool,
+}
+
+impl Display for Exec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.command.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Http {
+    /// HTTP request which is sent.
+    pub http: HttpRequest,
+}
+
+impl Display for Http {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.http.method.to_uppercase(), self.http.url)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HttpRequest {
+    /// The request method.
+    #[serde(default = "default_http_method")]
+    pub method: String,
+    /// The url to send the request to.
+    pub url: String,
+    /// The request headers.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// The request body.
+    pub body: Option<String>,
+    /// JSON request body (sent with the `application/json` content type).
+    pub json: Option<serde_json::Value>,
+    /// Status codes which indicate a success. By default, any 2xx status is a success.
+    #[serde(default)]
+    pub expect: Vec<u16>,
+}
+
+fn default_http_method() -> String {
+    "GET".to_string()
+}
+
+impl HttpRequest {
+    /// Whether the response status indicates a success
+    pub fn is_expected(&self, status: u16) -> bool {
+        if self.expect.is_empty() {
+            (200..300).contains(&status)
+        } else {
+            self.expect.contains(&status)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum TaskHandler {
     /// The task handler is a shell command.
     Shell(Shell),
+    /// The task handler is an HTTP request.
+    Http(Http),
+    /// The task handler is a script file.
+    Script(Script),
+    /// The task handler is a program executed without a shell.
+    Exec(Exec),
+    /// The task handler is another job.
+    Job(SubJob),
 }
 
 impl Display for TaskHandler {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             TaskHandler::Shell(shell) => write!(f, "{}", shell),
+            TaskHandler::Http(http) => write!(f, "{}", http),
+            TaskHandler::Script(script) => write!(f, "{}", script),
+            TaskHandler::Exec(exec) => write!(f, "{}", exec),
+            TaskHandler::Job(job) => write!(f, "{}", job),
         }
     }
 }
 
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Task {
     /// The identifier of the task.
     pub id: Option<String>,
     /// The name of the task.
     pub name: Option<String>,
-    /// Handler for the task.
+    /// Handler for the task (may be inherited from the template the task extends).
     #[serde(flatten)]
-    pub handler: TaskHandler,
+    pub handler: Option<TaskHandler>,
+    /// Name of the template task whose fields the task inherits.
+    pub extends: Option<String>,
     /// Environment variable overrides for the task.
     pub env: Option<Env>,
     /// Working directory for the task.
     pub cwd: Option<String>,
     /// Hooks for the task.
+    #[serde(default)]
+    #[schemars(schema_with = "hooks_schema")]
     pub hooks: Option<Hooks>,
     /// Execution policy for the task.
     pub policy: Option<ExecutionPolicy>,
+    /// Maximum time the task is allowed to run before it is terminated.
+    pub timeout: Option<HumanDuration>,
+    /// Retry policy for the task.
+    pub retry: Option<Retry>,
+    /// Ids of the tasks which need to complete before this task is executed.
+    /// If not set, the task depends on the task defined before it.
+    pub needs: Option<Vec<String>>,
+    /// Condition expression which must hold for the task to be executed.
+    #[serde(rename = "if")]
+    pub condition: Option<String>,
 }
 
 impl Task {
     pub fn get_name(&self) -> String {
-        self.name.clone().unwrap_or_else(|| self.handler.to_string())
+        self.name.clone()
+            .or_else(|| self.handler.as_ref().map(TaskHandler::to_string))
+            .unwrap_or_default()
+    }
+
+    /// Returns the task with the fields it does not set taken from the template.
+    /// The env is merged (with the task env taking precedence) and the id is never inherited.
+    pub fn inherit(self, template: &Task) -> Task {
+        let env = match (template.env.clone(), self.env) {
+            (Some(mut env), Some(overrides)) => {
+                env.extend(overrides);
+                Some(env)
+            }
+            (env, overrides) => overrides.or(env),
+        };
+        Task {
+            id: self.id,
+            name: self.name.or_else(|| template.name.clone()),
+            handler: self.handler.or_else(|| template.handler.clone()),
+            extends: template.extends.clone(),
+            env,
+            cwd: self.cwd.or_else(|| template.cwd.clone()),
+            hooks: self.hooks.or_else(|| template.hooks.clone()),
+            policy: self.policy.or(template.policy),
+            timeout: self.timeout.or(template.timeout),
+            retry: self.retry.or_else(|| template.retry.clone()),
+            needs: self.needs.or_else(|| template.needs.clone()),
+            condition: self.condition.or_else(|| template.condition.clone()),
+        }
+    }
+}
+
+/// Backoff strategy between task attempts
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Backoff {
+    /// Wait the same delay between all the attempts.
+    #[default]
+    Fixed,
+    /// Double the delay after each attempt.
+    Exponential,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Retry {
+    /// Maximum number of attempts (including the first one).
+    #[serde(default = "retry_attempts_default")]
+    pub attempts: u32,
+    /// Delay before the second attempt.
+    #[serde(default)]
+    pub delay: HumanDuration,
+    /// Backoff strategy for the delay of the subsequent attempts.
+    #[serde(default)]
+    pub backoff: Backoff,
+    /// Upper bound for the delay between attempts.
+    pub max_delay: Option<HumanDuration>,
+    /// Exit codes which should be retried. If not set, every failure is retried.
+    pub exit_codes: Option<Vec<i32>>,
+}
+
+impl Retry {
+    /// Whether a task failed with the given exit code should be retried
+    pub fn is_retryable(&self, exit_code: i32) -> bool {
+        self.exit_codes.as_ref().map(|codes| codes.contains(&exit_code)).unwrap_or(true)
+    }
+
+    /// Delay to wait after the given (1-based) attempt has failed
+    pub fn delay(&self, attempt: u32) -> std::time::Duration {
+        let delay = match self.backoff {
+            Backoff::Fixed => self.delay.as_duration(),
+            Backoff::Exponential => self.delay.as_duration()
+                .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1))),
+        };
+        match self.max_delay {
+            Some(max_delay) => delay.min(max_delay.as_duration()),
+            None => delay,
+        }
     }
 }
 
+fn retry_attempts_default() -> u32 {
+    3
+}
+
 /// List of tasks
 pub type Tasks = Vec<Task>;
+/// Template tasks by their name
+pub type Templates = BTreeMap<String, Task>;
 /// List of hooks
 pub type Hooks = HashMap<Hook, Tasks>;
 
+/// Schema of the hooks listing the hook names as properties
+fn hooks_schema(gen: &mut SchemaGenerator) -> Schema {
+    let tasks = gen.subschema_for::<Tasks>();
+    SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(ObjectValidation {
+            properties: Hook::ALL.iter().map(|hook| (hook.to_string(), tasks.clone())).collect(),
+            additional_properties: Some(Box::new(Schema::Bool(false))),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }.into()
+}
+
 fn true_default() -> bool {
     true
 }
@@ -209,7 +636,7 @@ fn false_default() -> bool {
     false
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LogOptions {
     /// Whether stdout should be logged.
     #[serde(default = "true_default")]
@@ -223,28 +650,91 @@ pub struct LogOptions {
     /// Whether internal logging should be logged.
     #[serde(default = "true_default")]
     pub internal: bool,
+    /// Log level of the internal logging (defaults to the job log level).
+    pub level: Option<LogLevel>,
+    /// Prepend a timestamp (wall clock time or the time elapsed since the task has started) to every line.
+    pub timestamps: Option<TimestampMode>,
+    /// Format of the timestamps (strftime syntax).
+    pub timestamp_format: Option<String>,
+    /// Prepend the id or the name of the task to every line.
+    pub prefix: Option<PrefixMode>,
+    /// Whether to keep the ansi escape sequences (defaults to true for the console if it is a terminal).
+    pub ansi: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampMode {
+    /// Wall clock time at which the line was written.
+    Wall,
+    /// Time elapsed since the task (or the job) has started.
+    Elapsed,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+impl TimestampMode {
+    /// Returns the default format of the timestamps
+    pub fn default_format(&self) -> &'static str {
+        match self {
+            TimestampMode::Wall => "%Y-%m-%d %H:%M:%S%.3f",
+            TimestampMode::Elapsed => "%H:%M:%S%.3f",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrefixMode {
+    /// Id of the task.
+    Id,
+    /// Name of the task.
+    Name,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Eq, PartialEq)]
 pub struct FileHandler {
     /// The file or directory (in split mode) to write to.
     pub output: Option<String>,
     /// Whether logs should be split into multiple files.
     #[serde(default = "false_default")]
     pub split: bool,
+    /// Maximum size of a log file. Once exceeded, the file is rotated (moved to `<file>.1`).
+    pub max_size: Option<ByteSize>,
+    /// Maximum number of rotated files to keep (defaults to 5).
+    pub max_files: Option<usize>,
+    /// Whether the rotated files are compressed with gzip.
+    #[serde(default = "false_default")]
+    pub compress: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Eq, PartialEq)]
+pub struct JsonHandler {
+    /// The file to write the events to (defaults to `events.jsonl`).
+    pub output: Option<String>,
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Eq, PartialEq)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum LogHandlerType {
     /// Log to file handler.
     File(FileHandler),
     /// Log to console handler.
     Console,
+    /// Log structured events as json lines handler.
+    Json(JsonHandler),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl LogHandlerType {
+    /// Returns the name of the handler type as used in the job file
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogHandlerType::File(_) => "file",
+            LogHandlerType::Console => "console",
+            LogHandlerType::Json(_) => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LogHandler {
     /// The name of the log handler.
     pub name: Option<String>,
@@ -266,9 +756,22 @@ impl LogHandler {
                 stderr: true,
                 hooks: true,
                 internal: true,
+                level: None,
+                timestamps: None,
+                timestamp_format: None,
+                prefix: None,
+                ansi: None,
             },
         }
     }
+
+    /// Whether the ansi escape sequences are kept in the output of the handler
+    pub fn ansi(&self) -> bool {
+        self.options.ansi.unwrap_or_else(|| match self.handler {
+            LogHandlerType::Console => std::io::stdout().is_terminal(),
+            _ => false,
+        })
+    }
 }
 
 
@@ -276,7 +779,7 @@ impl LogHandler {
 pub type LogHandlers = Vec<LogHandler>;
 
 /// Hooks
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum Hook {
     /// Before job execution.
@@ -293,6 +796,12 @@ pub enum Hook {
     OnSuccess,
 }
 
+impl Hook {
+    pub const ALL: [Hook; 6] = [
+        Hook::BeforeJob, Hook::AfterJob, Hook::BeforeTask, Hook::AfterTask, Hook::OnFailure, Hook::OnSuccess,
+    ];
+}
+
 impl Display for Hook {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", format!("{:?}", self).to_snake_case())
@@ -300,10 +809,11 @@ impl Display for Hook {
 }
 
 /// Execution policy
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecutionPolicy {
     /// Execute the task only if no other task has failed.
+    #[default]
     NoPriorFailed,
     /// Execute the task only if prior task has succeeded.
     PriorSuccess,
@@ -311,19 +821,13 @@ pub enum ExecutionPolicy {
     Always,
 }
 
-impl Default for ExecutionPolicy {
-    fn default() -> Self {
-        ExecutionPolicy::NoPriorFailed
-    }
-}
-
 impl Display for ExecutionPolicy {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", format!("{:?}", self).to_snake_case())
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Job {
     /// The identifier of the job.
     pub id: Option<String>,
@@ -336,13 +840,169 @@ pub struct Job {
     /// List of tasks for the job.
     pub tasks: Tasks,
     /// List of global hooks.
+    #[serde(default)]
+    #[schemars(schema_with = "hooks_schema")]
     pub hooks: Option<Hooks>,
     /// List of log handlers for the job.
     pub logging: Option<LogHandlers>,
     /// Global execution policy for the job.
     #[serde(default)]
     pub policy: ExecutionPolicy,
+    /// Maximum duration of the whole job. Once it expires, the running tasks are terminated and the remaining tasks are not started.
+    pub timeout: Option<HumanDuration>,
     /// Global option overrides for the job.
     pub options: Option<Options>,
+    /// Lock preventing concurrent runs of the job.
+    pub lock: Option<Lock>,
+    /// Secrets which are masked in the logs and summaries.
+    pub secrets: Option<Secrets>,
+    /// Files whose tasks, hooks, env, log handlers and templates are merged into the job.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Template tasks which the tasks can extend by their name.
+    pub templates: Option<Templates>,
+    /// Canonical path of the job file (if the job is read from a file)
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
 }
 
+impl Job {
+    /// Reads and parses a job file, returning the job together with the file contents.
+    /// Jobs without an explicit id get the id from their file name.
+    pub fn read(job_path: &str) -> Result<(Job, String)> {
+        let contents = std::fs::read_to_string(job_path)
+            .with_context(|| format!("Failed to read job file: {}", job_path))?;
+        let mut job: Job = serde_yaml::from_str(&contents)
+            .map_err(|e| anyhow!("Failed to parse job file: Error {}", e))?;
+        job.id = Some(job.id.unwrap_or_else(|| job_id_from_path(job_path)));
+        job.path = std::fs::canonicalize(job_path).ok();
+        Ok((job, contents))
+    }
+
+    /// Returns the JSON schema of the job file format
+    pub fn schema() -> RootSchema {
+        let mut schema = schema_for!(Job);
+        // The schema should not depend on the machine it is generated on
+        if let Some(Schema::Object(options)) = schema.definitions.get_mut("Options") {
+            for name in MACHINE_DEPENDENT_OPTIONS {
+                if let Some(Schema::Object(option)) = options.object().properties.get_mut(*name) {
+                    option.metadata().default = None;
+                }
+            }
+        }
+        schema
+    }
+}
+
+/// Fragment of a job which is merged into the jobs including it
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Include {
+    /// Environment variables for the job (overridden by the job env).
+    pub env: Option<Env>,
+    /// Tasks added before the tasks of the job.
+    #[serde(default)]
+    pub tasks: Tasks,
+    /// Hooks added before the hooks of the job.
+    pub hooks: Option<Hooks>,
+    /// Log handlers added before the log handlers of the job.
+    pub logging: Option<LogHandlers>,
+    /// Template tasks (overridden by the templates of the job).
+    pub templates: Option<Templates>,
+}
+
+impl Include {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read included file: {}", path.display()))?;
+        serde_yaml::from_str(&contents)
+            .map_err(|e| anyhow!("Failed to parse included file: {}. Error {}", path.display(), e))
+    }
+}
+
+/// Behavior when the job lock is held by another run
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LockBehavior {
+    /// Exit without running the job.
+    #[default]
+    Skip,
+    /// Wait until the lock is released.
+    Wait,
+    /// Exit with an error.
+    Fail,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Lock {
+    /// Path to the lock file. Defaults to a file named after the job id in the temp path.
+    pub path: Option<String>,
+    /// Behavior when the lock is held by another run.
+    #[serde(default)]
+    pub behavior: LockBehavior,
+    /// Maximum time to wait for the lock.
+    pub timeout: Option<HumanDuration>,
+}
+
+/// Secret values which are masked with `***` in the logs and summaries
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Secrets {
+    /// Names of the environment variables holding secrets.
+    #[serde(default)]
+    pub env: Vec<String>,
+    /// Path to a dotenv file with secrets. All its variables are added to the env and treated as secrets.
+    pub file: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::common::HumanDuration;
+    use test_case::test_case;
+    use crate::config::{Backoff, Retry, Summary, SummaryFormat};
+
+    #[test]
+    fn test_retry_delay() {
+        let mut retry = Retry {
+            attempts: 5,
+            delay: HumanDuration::from_secs(2),
+            backoff: Backoff::Fixed,
+            max_delay: None,
+            exit_codes: None,
+        };
+        assert_eq!(retry.delay(1), Duration::from_secs(2));
+        assert_eq!(retry.delay(4), Duration::from_secs(2));
+
+        retry.backoff = Backoff::Exponential;
+        assert_eq!(retry.delay(1), Duration::from_secs(2));
+        assert_eq!(retry.delay(3), Duration::from_secs(8));
+
+        retry.max_delay = Some(HumanDuration::from_secs(5));
+        assert_eq!(retry.delay(3), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_retry_exit_codes() {
+        let mut retry = Retry {
+            attempts: 3,
+            delay: HumanDuration::default(),
+            backoff: Backoff::Fixed,
+            max_delay: None,
+            exit_codes: None,
+        };
+        assert!(retry.is_retryable(1));
+        assert!(retry.is_retryable(-1));
+
+        retry.exit_codes = Some(vec![75]);
+        assert!(retry.is_retryable(75));
+        assert!(!retry.is_retryable(1));
+    }
+
+    #[test_case("junit", SummaryFormat::Junit, None ; "format only")]
+    #[test_case("markdown=./report.md", SummaryFormat::Markdown, Some("./report.md") ; "with output")]
+    fn test_parse_summary(s: &str, format: SummaryFormat, output: Option<&str>) {
+        let summary: Summary = s.parse().unwrap();
+        assert_eq!(summary, Summary { format, output: output.map(String::from) });
+        assert!("html".parse::<Summary>().is_err());
+    }
+}
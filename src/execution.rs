@@ -1,12 +1,27 @@
-use std::{io, fs::File, io::{BufReader, Read}, path::{PathBuf}, process::Stdio, os::unix::io::{AsRawFd, FromRawFd}, thread};
+use std::{io, io::{BufReader, Read}, path::{Path, PathBuf}, process::Stdio, os::unix::process::CommandExt, thread};
+use crate::config::Hook;
+use crate::flow::FlowIterator;
+use crate::expression::Scope;
+use std::collections::HashMap;
 use std::thread::JoinHandle;
-use std::time::Instant;
-use crate::{flow, flow::CommandId, logging::{MultiOutputStream, MultiWriter}, common::Env, config, config::{ExecutionPolicy, Shell, TaskHandler}, logging::{ActionShell, InputStream}, flow::Command, logging::ActionCommandStart};
-use anyhow::{anyhow, Context as AnyhowContext, Result};
-use chrono::{Local};
-use crossbeam_channel::{bounded, Sender};
-use crate::logging::{ActionCommandEnd, ActionSummary, Logger};
-use crate::utils::{resolve_cwd, with_tempfile};
+use std::time::{Duration, Instant};
+use crate::{flow, flow::CommandId, logging::{MultiOutputStream, MultiWriter}, common::Env, config, config::{Exec, ExecutionPolicy, ExitCodePolicy, Http, LockBehavior, Script, Shell, SubJob, TaskHandler}, logging::{ActionShell, InputStream}, flow::Command, logging::ActionCommandStart};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use crossbeam_channel::{bounded, unbounded, RecvTimeoutError, Sender};
+use nix::{sys::signal::{killpg, Signal}, unistd::Pid};
+use crate::history::{History, RunRecord};
+use crate::lock::FileLock;
+use crate::state::{RunArgs, RunState};
+use serde::{Serialize, Deserialize};
+use crate::logging::{ActionCommandEnd, ActionCommandRetry, ActionHttp, ActionHttpResponse, ActionJobEnd, ActionJobLocked, ActionJobStart, ActionJobResumed, ActionRunPruned, ActionSummary, Logger};
+use crate::retention;
+use crate::http;
+use crate::script;
+use crate::logging::pprint;
+use crate::summary;
+use crate::template;
+use crate::utils::{expand_env, mask_text, resolve_cwd, with_tempfile};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum ExecutionState {
@@ -14,28 +29,209 @@ pub enum ExecutionState {
     Failed,
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Success,
+    Failed,
+    /// The job was not executed because another run holds its lock
+    Skipped,
+}
+
+/// Overall result of a job execution
 #[derive(Debug, Clone)]
+pub struct JobResult {
+    pub status: JobStatus,
+    /// Exit code the process should exit with
+    pub exit_code: i32,
+    /// Results of all the commands in the order of execution
+    pub results: Vec<(CommandId, ExecutionResult)>,
+}
+
+impl JobResult {
+    pub fn new(flow: &flow::Flow, options: &config::Options, results: Vec<(CommandId, ExecutionResult)>) -> Self {
+        // Hooks only count towards the job result if their failure is considered fatal
+        let relevant: Vec<&ExecutionResult> = results.iter()
+            .filter(|(id, _)| options.fail_on_hook_failure || !flow.command(id).expect("Command not found").is_hook)
+            .map(|(_, result)| result)
+            .filter(|result| !result.is_aborted() && !result.is_skipped())
+            .collect();
+        let failure_code = |result: &ExecutionResult| match result.exit_code {
+            code if result.is_success() => code,
+            // Timed out or signaled processes and out of range codes are reported as a generic failure
+            code if code <= 0 || code > 255 => 1,
+            code => code,
+        };
+
+        let first_failure = relevant.iter().find(|result| result.is_failed());
+        let status = if first_failure.is_some() { JobStatus::Failed } else { JobStatus::Success };
+        let exit_code = match options.exit_code {
+            ExitCodePolicy::FirstFailure => first_failure.map(|result| failure_code(result)).unwrap_or(0),
+            ExitCodePolicy::Last => relevant.last().map(|result| failure_code(result)).unwrap_or(0),
+            ExitCodePolicy::AlwaysZero => 0,
+            ExitCodePolicy::Fixed(code) => if status == JobStatus::Failed { code } else { 0 },
+        };
+
+        JobResult { status, exit_code, results }
+    }
+
+    /// Result of a job which was not executed at all
+    pub fn skipped() -> Self {
+        JobResult { status: JobStatus::Skipped, exit_code: 0, results: Vec::new() }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == JobStatus::Success
+    }
+
+    /// Masks the secret values within the rendered names of the commands
+    pub fn mask_secrets(&mut self, secrets: &[String]) {
+        for (_, result) in &mut self.results {
+            result.mask_secrets(secrets, false);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionResult {
     pub command_id: CommandId,
     pub focus_id: Option<CommandId>,
+    /// Name of the command as it was rendered for execution
+    pub name: Option<String>,
+    #[serde(default)]
+    pub is_hook: bool,
     pub exit_code: i32,
     pub aborted: bool,
+    pub timed_out: bool,
+    pub skipped: bool,
+    /// Whether the command was skipped, since it is not selected for execution
+    #[serde(default)]
+    pub deselected: bool,
+    pub attempts: u32,
     pub duration: Option<std::time::Duration>,
+    pub outputs: Env,
+    /// Results of the commands of the sub job run by the command
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nested: Vec<ExecutionResult>,
 }
 
 impl ExecutionResult {
+    pub fn new(command_id: CommandId, focus_id: Option<CommandId>) -> Self {
+        Self {
+            command_id,
+            focus_id,
+            name: None,
+            is_hook: false,
+            exit_code: 0,
+            aborted: false,
+            timed_out: false,
+            skipped: false,
+            deselected: false,
+            attempts: 0,
+            duration: None,
+            outputs: Env::default(),
+            nested: Vec::new(),
+        }
+    }
+
     pub fn is_success(&self) -> bool {
-        self.exit_code == 0
+        self.exit_code == 0 && !self.timed_out
     }
 
     pub fn is_aborted(&self) -> bool {
         self.aborted
     }
+
+    pub fn is_timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    pub fn is_skipped(&self) -> bool {
+        self.skipped
+    }
+
+    pub fn is_deselected(&self) -> bool {
+        self.deselected
+    }
+
+    /// Whether the command was executed and did not succeed
+    pub fn is_failed(&self) -> bool {
+        !self.is_success() && !self.is_aborted() && !self.is_skipped()
+    }
+
+    /// Masks the secret values within the rendered name of the command and of its sub job commands.
+    /// The values of the outputs are masked as well if requested.
+    pub fn mask_secrets(&mut self, secrets: &[String], outputs: bool) {
+        if let Some(name) = &mut self.name {
+            *name = mask_text(name, secrets);
+        }
+        if outputs {
+            self.outputs = self.outputs.iter()
+                .map(|(key, value)| (key.clone(), mask_text(value, secrets)))
+                .collect();
+        }
+        for nested in &mut self.nested {
+            nested.mask_secrets(secrets, outputs);
+        }
+    }
+
+    /// Returns the status of the result as used in the condition expressions
+    pub fn status(&self) -> &'static str {
+        if self.is_aborted() {
+            "aborted"
+        } else if self.is_deselected() {
+            "deselected"
+        } else if self.is_skipped() {
+            "skipped"
+        } else if self.is_timed_out() {
+            "timed_out"
+        } else if self.is_success() {
+            "success"
+        } else {
+            "failure"
+        }
+    }
+}
+
+/// Information about the current job run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunInfo {
+    pub job_id: String,
+    pub job_name: String,
+    /// Unique identifier of the run (also used as the log directory name)
+    pub run_id: String,
+    pub started_at: DateTime<Local>,
+    /// Path to the job file the run was started from
+    pub job_path: Option<PathBuf>,
+    /// Hash of the job file contents (used to detect changes on resume)
+    pub job_hash: Option<String>,
+    /// What has started the run (e.g. manual, automated or resume)
+    pub trigger: String,
+    /// Command line arguments the run was started with
+    #[serde(default)]
+    pub args: RunArgs,
+}
+
+impl RunInfo {
+    pub fn new(job_id: &str, job_name: &str) -> Self {
+        let started_at = Local::now();
+        RunInfo {
+            job_id: job_id.to_string(),
+            job_name: job_name.to_string(),
+            run_id: format!("{}_{}", job_id, started_at.format("%Y-%m-%dT%H:%M:%S")),
+            started_at,
+            job_path: None,
+            job_hash: None,
+            trigger: "manual".to_string(),
+            args: RunArgs::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ExecutionContext {
     pub options: config::Options,
+    pub run: RunInfo,
     pub env: Env,
     pub cwd: PathBuf,
     pub log_dir: PathBuf,
@@ -44,12 +240,23 @@ pub struct ExecutionContext {
     pub current: Option<(CommandId, Command)>,
     pub focus: Option<CommandId>,
     pub previous: Option<ExecutionResult>,
+    /// Results of the completed main commands visible to the current command
+    pub results: HashMap<CommandId, ExecutionResult>,
+    /// Names of the environment variables holding secrets
+    pub secret_env: Vec<String>,
+    /// Secret values loaded from the secrets file
+    pub secret_values: Vec<String>,
+    /// Job files of the jobs running this job as a sub job (the outermost first)
+    pub parents: Vec<PathBuf>,
+    /// Time at which the job timeout expires
+    pub deadline: Option<Instant>,
 }
 
 impl ExecutionContext {
-    pub fn new(options: config::Options, cwd: PathBuf) -> Self {
+    pub fn new(options: config::Options, run: RunInfo, cwd: PathBuf) -> Self {
         Self {
             options,
+            run,
             cwd,
             env: Env::default(),
             log_dir: PathBuf::new(),
@@ -58,6 +265,50 @@ impl ExecutionContext {
             current: None,
             focus: None,
             previous: None,
+            results: HashMap::new(),
+            secret_env: Vec::new(),
+            secret_values: Vec::new(),
+            parents: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    /// Whether the job is run as a sub job of another job
+    pub fn is_nested(&self) -> bool {
+        !self.parents.is_empty()
+    }
+
+    /// Returns the secret values to mask in the output (longest first).
+    /// Values spanning multiple lines are split, since the output is masked line by line.
+    pub fn secrets(&self) -> Vec<String> {
+        let values = self.secret_env.iter()
+            .filter_map(|name| self.env(name))
+            .chain(self.secret_values.iter().cloned())
+            .collect::<Vec<_>>();
+        let mut secrets: Vec<String> = values.iter()
+            .flat_map(|value| value.lines())
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect();
+        secrets.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        secrets.dedup();
+        secrets
+    }
+
+    /// Whether the job timeout has expired (the hooks are not limited by it)
+    pub fn is_expired(&self, command: &Command) -> bool {
+        !command.is_hook && self.deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false)
+    }
+
+    /// Returns the timeout of the command, limited by the time left until the job timeout expires
+    pub fn timeout(&self, command: &Command) -> Option<Duration> {
+        let timeout = command.timeout.or(self.options.timeout).map(Duration::from);
+        let remaining = self.deadline
+            .filter(|_| !command.is_hook)
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        match (timeout, remaining) {
+            (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+            (timeout, remaining) => timeout.or(remaining),
         }
     }
 
@@ -74,6 +325,34 @@ impl ExecutionContext {
     }
 }
 
+impl Scope for ExecutionContext {
+    fn env(&self, name: &str) -> Option<String> {
+        self.current.as_ref()
+            .and_then(|(_, command)| command.env.get(name))
+            .or_else(|| self.env.get(name))
+            .cloned()
+    }
+
+    fn task(&self, id: &str) -> Option<&ExecutionResult> {
+        self.results.get(id)
+    }
+
+    fn builtin(&self, name: &str) -> Option<String> {
+        match name {
+            "job.id" => Some(self.run.job_id.clone()),
+            "job.name" => Some(self.run.job_name.clone()),
+            "run.id" => Some(self.run.run_id.clone()),
+            "run.date" => Some(self.run.started_at.format("%Y-%m-%d").to_string()),
+            "run.time" => Some(self.run.started_at.format("%H:%M:%S").to_string()),
+            "run.timestamp" => Some(self.run.started_at.format("%Y%m%dT%H%M%S").to_string()),
+            "run.log_dir" => Some(self.log_dir.to_string_lossy().to_string()),
+            "task.id" => self.current.as_ref().map(|(id, _)| id.clone()),
+            "task.name" => self.current.as_ref().map(|(_, command)| command.name.clone()),
+            _ => None,
+        }
+    }
+}
+
 
 /// Reads the contents of a file into a buffer.
 fn read_buffer<T: std::io::Read>(source: &mut BufReader<T>, buffer: &mut [u8]) -> io::Result<Option<usize>> {
@@ -98,7 +377,7 @@ fn capture_stream<T: std::io::Read + std::marker::Send + 'static>(
         loop {
             match read_buffer(&mut source, &mut buffer) {
                 Ok(None) => break,
-                Ok(Some(size)) if size == 0 => {
+                Ok(Some(0)) => {
                     break;
                 }
                 Ok(Some(size)) => {
@@ -114,17 +393,27 @@ fn capture_stream<T: std::io::Read + std::marker::Send + 'static>(
 
 const BUFFER_SIZE: usize = 1024; // 1 KB
 
+/// Sends a signal to the whole process group of the child.
+fn signal_process_group(child: &std::process::Child, signal: Signal) -> Result<()> {
+    match killpg(Pid::from_raw(child.id() as i32), signal) {
+        // The process group has already exited
+        Err(nix::Error::ESRCH) => Ok(()),
+        result => result.map_err(|e| anyhow!("Failed to send {} to process group: {}", signal, e)),
+    }
+}
+
 /// Responsible for executing a command and capturing its output.
+/// If a timeout is given, the process group of the child is terminated with SIGTERM once it
+/// expires, and killed with SIGKILL if it is still alive after the grace period.
+/// Returns whether the command has timed out.
 pub fn capture_command(
-    child: &std::process::Child,
+    child: &mut std::process::Child,
     output: &mut MultiOutputStream,
-) -> Result<()> {
-    let stdout = BufReader::new(unsafe {
-        File::from_raw_fd(child.stdout.as_ref().unwrap().as_raw_fd())
-    });
-    let stderr = BufReader::new(unsafe {
-        File::from_raw_fd(child.stderr.as_ref().unwrap().as_raw_fd())
-    });
+    timeout: Option<Duration>,
+    grace_period: Duration,
+) -> Result<bool> {
+    let stdout = BufReader::new(child.stdout.take().expect("Child stdout is not piped"));
+    let stderr = BufReader::new(child.stderr.take().expect("Child stderr is not piped"));
 
     let (s1, r) = bounded(4);
     let s2 = s1.clone();
@@ -132,14 +421,35 @@ pub fn capture_command(
     let thread_stdout = capture_stream(stdout, s1, InputStream::Stdout);
     let thread_stderr = capture_stream(stderr, s2, InputStream::Stderr);
 
-    for (buffer, size, stream) in r {
-        output.write_stream(stream, &buffer[..size])?;
+    let mut timed_out = false;
+    let mut deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let message = match deadline {
+            Some(deadline) => r.recv_deadline(deadline),
+            None => r.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match message {
+            Ok((buffer, size, stream)) => {
+                output.write_stream(stream, &buffer[..size])?;
+            }
+            Err(RecvTimeoutError::Timeout) if !timed_out => {
+                timed_out = true;
+                signal_process_group(child, Signal::SIGTERM)?;
+                deadline = Some(Instant::now() + grace_period);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                signal_process_group(child, Signal::SIGKILL)?;
+                deadline = None;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
     }
 
     thread_stdout.join().unwrap()?;
     thread_stderr.join().unwrap()?;
 
-    Ok(())
+    Ok(timed_out)
 }
 
 pub trait ExecutableHandler {
@@ -158,13 +468,6 @@ impl ExecutableHandler for Shell {
         context: &mut ExecutionContext,
         logger: &mut Logger,
     ) -> Result<ExecutionResult> {
-        // Build env
-        let mut env = context.env.clone();
-        env.extend(command.env.clone());
-
-        // Build cwd
-        let cwd = resolve_cwd(&context.cwd, command.cwd.as_ref());
-
         // Build shell
         let shell = self.shell.clone().unwrap_or_else(|| context.options.shell.clone());
         let shell_path = self.shell_path.as_ref().or_else(|| {
@@ -177,44 +480,294 @@ impl ExecutableHandler for Shell {
         let shell_program = shell.executable(shell_path)?;
         let shell_args = shell.args(shell_path, self.run.clone())?;
 
+        execute_program(&self.run, shell_program, shell_args, command, context, logger)
+    }
+}
+
+impl ExecutableHandler for Script {
+    fn execute(
+        &self,
+        command: &flow::Command,
+        context: &mut ExecutionContext,
+        logger: &mut Logger,
+    ) -> Result<ExecutionResult> {
+        let path = resolve_job_file(&self.script, command, context);
+        let (program, args) = script::resolve(
+            self, &path, &context.options.shell, context.options.shell_path.as_ref(),
+        )?;
+
+        let text = pprint::command_line(&program, &args);
+        execute_program(&text, program, args, command, context, logger)
+    }
+}
+
+impl ExecutableHandler for Exec {
+    fn execute(
+        &self,
+        command: &flow::Command,
+        context: &mut ExecutionContext,
+        logger: &mut Logger,
+    ) -> Result<ExecutionResult> {
+        // Expand the env references without a shell, so the arguments need no quoting
+        let mut env = context.env.clone();
+        env.extend(command.env.clone());
+        let mut argv = self.command.iter().map(|arg| if self.expand_env {
+            expand_env(arg, |name| env.get(name).cloned())
+        } else {
+            arg.clone()
+        });
+        let program = argv.next()
+            .ok_or_else(|| anyhow!("Task \"{}\" has an empty command", command.name))?;
+        let args: Vec<String> = argv.collect();
+
+        let text = pprint::command_line(&program, &args);
+        execute_program(&text, program, args, command, context, logger)
+    }
+}
+
+impl ExecutableHandler for SubJob {
+    fn execute(
+        &self,
+        command: &flow::Command,
+        context: &mut ExecutionContext,
+        logger: &mut Logger,
+    ) -> Result<ExecutionResult> {
+        // The sub job runs within the task cwd
+        let cwd = resolve_cwd(&context.cwd, command.cwd.as_ref());
+        let path = resolve_job_file(&self.job, command, context);
+
+        let now = Instant::now();
+        let mut result = ExecutionResult {
+            attempts: 1,
+            ..ExecutionResult::new(context.current_command_id().clone(), context.focus.clone())
+        };
+
+        // A job which can not be loaded fails the task
+        let (path, flow) = match load_sub_job(&path, &cwd, context) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                logger.mut_output().write_stream(InputStream::Stderr, format!("{}\n", e).as_bytes())?;
+                result.exit_code = 2;
+                result.duration = Some(now.elapsed());
+                return Ok(result);
+            }
+        };
+
+        // The task env overrides the sub job env and the task timeout limits the sub job as a whole
+        let mut executor = Executor::nested(&flow, context, path, cwd, command.env.clone());
+        let deadline = command.timeout.map(|timeout| now + Duration::from(timeout));
+        if let Some(deadline) = deadline {
+            executor.context.deadline = Some(executor.context.deadline.map_or(deadline, |parent| parent.min(deadline)));
+        }
+        logger.flush()?;
+        let mut nested_logger = logger.nest(context.current_command_id());
+        let job_result = executor.execute(&mut nested_logger);
+        nested_logger.flush()?;
+
+        match job_result {
+            Ok(job_result) => {
+                result.exit_code = match job_result.status {
+                    // The job may be configured to exit with zero on failure
+                    JobStatus::Failed if job_result.exit_code == 0 => 1,
+                    _ => job_result.exit_code,
+                };
+                result.nested = job_result.results.into_iter().map(|(_, result)| result).collect();
+                if !result.is_success() && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    result.exit_code = -1;
+                    result.timed_out = true;
+                }
+            }
+            // A sub job which fails to execute (e.g. its lock can not be acquired) fails the task
+            Err(e) => {
+                logger.mut_output().write_stream(InputStream::Stderr, format!("{}\n", e).as_bytes())?;
+                result.exit_code = 2;
+            }
+        }
+        result.duration = Some(now.elapsed());
+        Ok(result)
+    }
+}
+
+/// Resolves a file referenced by a task relative to the directory of the job file.
+/// If the job file is not known (e.g. the job is read from stdin), it is resolved relative to the task cwd instead.
+fn resolve_job_file(file: &String, command: &flow::Command, context: &ExecutionContext) -> PathBuf {
+    let base = match context.run.job_path.as_ref().and_then(|path| path.parent()) {
+        Some(job_dir) => job_dir.to_path_buf(),
+        None => resolve_cwd(&context.cwd, command.cwd.as_ref()),
+    };
+    resolve_cwd(&base, Some(file)).components().collect()
+}
+
+/// Loads the sub job file and parses it to a flow (within the given cwd).
+/// Fails if the job is already running as one of the parents, since it would recurse indefinitely.
+fn load_sub_job(path: &Path, cwd: &Path, context: &ExecutionContext) -> Result<(PathBuf, flow::Flow)> {
+    let path = std::fs::canonicalize(path)
+        .map_err(|e| anyhow!("Failed to read job file: {}. Error: {}", path.display(), e))?;
+    let chain: Vec<&PathBuf> = context.parents.iter().chain(context.run.job_path.iter()).collect();
+    if chain.contains(&&path) {
+        let chain: Vec<String> = chain.iter().chain([&&path]).map(|job| job.display().to_string()).collect();
+        return Err(anyhow!("Recursive sub job: {}", chain.join(" -> ")));
+    }
+    let (job, _) = config::Job::read(&path.to_string_lossy())?;
+    let flow = flow::Flow::parse_in(&job, cwd)
+        .map_err(|e| anyhow!("Failed parsing job: {}", e))?;
+    Ok((path, flow))
+}
+
+/// Exit code of a command whose program can not be started (as used by the shells for a missing command)
+const EXIT_CODE_NOT_EXECUTED: i32 = 127;
+
+/// Executes a program within the command env and cwd, and captures its output.
+/// The text is the command as it is announced in the logs.
+fn execute_program(
+    text: &str,
+    program: String,
+    args: Vec<String>,
+    command: &flow::Command,
+    context: &mut ExecutionContext,
+    logger: &mut Logger,
+) -> Result<ExecutionResult> {
+    // Build env
+    let mut env = context.env.clone();
+    env.extend(command.env.clone());
+
+    // Build cwd
+    let cwd = resolve_cwd(&context.cwd, command.cwd.as_ref());
+
+    // Announce execution
+    logger.log_action(ActionShell {
+        command: text,
+        env: &env,
+    })?;
+
+    // Time execution
+    let now = Instant::now();
+
+    let timeout = context.timeout(command);
+
+    let (exit_code, timed_out) = if context.options.dry_run {
+        // Always succeed on dry run
+        (0, false)
+    } else {
+        // Build command
+        let mut process = std::process::Command::new(program);
+        process
+            .args(&args)
+            .envs(env)
+            .current_dir(cwd)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // Run in a separate process group, so that the whole group can be terminated on timeout
+        if timeout.is_some() {
+            process.process_group(0);
+        }
+        // A program which can not be started (e.g. a missing shell) fails the task like a shell would
+        let mut child = match process.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                logger.mut_output().write_stream(
+                    InputStream::Stderr, format!("Failed to execute command: {}. Error: {}\n", text, e).as_bytes(),
+                )?;
+                return Ok(ExecutionResult {
+                    exit_code: EXIT_CODE_NOT_EXECUTED,
+                    attempts: 1,
+                    duration: Some(now.elapsed()),
+                    ..ExecutionResult::new(context.current_command_id().clone(), context.focus.clone())
+                });
+            }
+        };
+
+        // Execute command, capture its output and return its exit code
+        let timed_out = capture_command(
+            &mut child, logger.mut_output(), timeout, context.options.kill_grace_period.into()
+        )?;
+        (child.wait()?.code().unwrap_or(-1), timed_out)
+    };
+
+    Ok(ExecutionResult {
+        exit_code,
+        timed_out,
+        attempts: 1,
+        duration: Some(now.elapsed()),
+        ..ExecutionResult::new(context.current_command_id().clone(), context.focus.clone())
+    })
+}
+
+/// Maximum size of the response body stored in the task outputs
+const MAX_BODY_OUTPUT: usize = 64 * 1024;
+
+impl ExecutableHandler for Http {
+    fn execute(
+        &self,
+        command: &flow::Command,
+        context: &mut ExecutionContext,
+        logger: &mut Logger,
+    ) -> Result<ExecutionResult> {
+        let request = &self.http;
+
         // Announce execution
-        logger.log_action(ActionShell {
-            handler: self,
-            env: &env,
-            cwd: &cwd,
-            shell_program: &shell_program,
-            shell_args: shell_args.as_slice(),
-        })?;
+        logger.log_action(ActionHttp { request })?;
 
         // Time execution
         let now = Instant::now();
-
-        let exit_code = if context.options.dry_run {
-            // Always succeed on dry run
-            0
-        } else {
-            // Build command
-            let mut child = std::process::Command::new(shell_program)
-                .args(&shell_args)
-                .envs(env)
-                .current_dir(cwd)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .with_context(|| format!("Failed to execute command: {}", self.run))?;
-
-            // Execute command, capture its output and return its exit code
-            capture_command(&child, logger.mut_output())?;
-            child.wait()?.code().unwrap_or(-1)
+        let mut result = ExecutionResult {
+            attempts: 1,
+            ..ExecutionResult::new(context.current_command_id().clone(), context.focus.clone())
         };
 
-        Ok(ExecutionResult {
-            command_id: context.current_command_id().clone(),
-            focus_id: context.focus.clone(),
-            exit_code,
-            aborted: false,
-            duration: Some(now.elapsed()),
-        })
+        // Always succeed on dry run
+        if !context.options.dry_run {
+            let mut headers: Vec<(String, String)> = request.headers.clone().into_iter().collect();
+            let body = match (&request.body, &request.json) {
+                (_, Some(json)) => {
+                    if !headers.iter().any(|(key, _)| key.eq_ignore_ascii_case("content-type")) {
+                        headers.push(("Content-Type".to_string(), "application/json".to_string()));
+                    }
+                    serde_json::to_vec(json)?
+                }
+                (Some(body), None) => body.clone().into_bytes(),
+                (None, None) => Vec::new(),
+            };
+            // An invalid (rendered) url fails the task like any other request error
+            let response = http::Url::parse(&request.url).and_then(|url| {
+                let http_request = http::Request {
+                    method: request.method.to_uppercase(),
+                    url,
+                    headers,
+                    body,
+                };
+                http::send(&http_request, context.timeout(command))
+            });
+            match response {
+                Ok(response) => {
+                    // The response body is logged as the output of the task
+                    let output = logger.mut_output();
+                    output.write_stream(InputStream::Stdout, &response.body)?;
+                    if !response.body.is_empty() && !response.body.ends_with(b"\n") {
+                        output.write_stream(InputStream::Stdout, b"\n")?;
+                    }
+
+                    let expected = request.is_expected(response.status);
+                    logger.log_action(ActionHttpResponse { response: &response, expected })?;
+                    result.exit_code = if expected { 0 } else { 1 };
+
+                    let body = &response.body[..response.body.len().min(MAX_BODY_OUTPUT)];
+                    result.outputs.insert(ENV_HTTP_STATUS.to_string(), response.status.to_string());
+                    result.outputs.insert(ENV_HTTP_BODY.to_string(), String::from_utf8_lossy(body).to_string());
+                }
+                Err(e) if e.is::<http::TimeoutError>() => {
+                    result.exit_code = -1;
+                    result.timed_out = true;
+                }
+                Err(e) => {
+                    logger.mut_output().write_stream(InputStream::Stderr, format!("{}\n", e).as_bytes())?;
+                    result.exit_code = 2;
+                }
+            }
+        }
+
+        result.duration = Some(now.elapsed());
+        Ok(result)
     }
 }
 
@@ -222,6 +775,10 @@ impl ExecutableHandler for TaskHandler {
     fn execute(&self, command: &Command, context: &mut ExecutionContext, logger: &mut Logger) -> Result<ExecutionResult> {
         match self {
             TaskHandler::Shell(handler) => handler.execute(command, context, logger),
+            TaskHandler::Http(handler) => handler.execute(command, context, logger),
+            TaskHandler::Script(handler) => handler.execute(command, context, logger),
+            TaskHandler::Exec(handler) => handler.execute(command, context, logger),
+            TaskHandler::Job(handler) => handler.execute(command, context, logger),
         }
     }
 }
@@ -242,12 +799,69 @@ pub const ENV_JOB_ID: &str = "NAUMAN_JOB_ID";
 pub const ENV_TASK_NAME: &str = "NAUMAN_TASK_NAME";
 pub const ENV_TASK_ID: &str = "NAUMAN_TASK_ID";
 pub const ENV_OUTPUT_FILE: &str = "NAUMAN_OUTPUT_FILE";
+pub const ENV_ATTEMPT: &str = "NAUMAN_ATTEMPT";
+/// Outputs of the http tasks
+pub const ENV_HTTP_STATUS: &str = "HTTP_STATUS";
+pub const ENV_HTTP_BODY: &str = "HTTP_BODY";
+
+
+/// Outcome of a main routine task execution including its hooks
+#[derive(Debug, Clone)]
+pub struct TaskOutcome {
+    /// Result of the task itself
+    pub result: ExecutionResult,
+    /// Results of the task and its hooks in the order of execution
+    pub results: Vec<(CommandId, ExecutionResult)>,
+    /// Environment variables set during the task execution (i.e. outputs)
+    pub outputs: Env,
+}
+
+impl TaskOutcome {
+    /// Outcome of a task which is not selected for execution
+    pub fn deselected(command_id: &CommandId) -> Self {
+        let result = ExecutionResult { skipped: true, deselected: true, ..ExecutionResult::new(command_id.clone(), None) };
+        TaskOutcome { result: result.clone(), results: vec![(command_id.clone(), result)], outputs: Env::default() }
+    }
+}
+
+/// Executes a main routine task including its hooks within the given context
+fn execute_task(
+    flow: &flow::Flow,
+    context: ExecutionContext,
+    command_id: &CommandId,
+    logger: &mut Logger,
+) -> Result<TaskOutcome> {
+    let initial_env = context.env.clone();
+    let mut executor = Executor { flow, context, resumed: None, overrides: Env::default() };
 
+    let results = executor.execute_routine(flow.iter_task(command_id), logger)?;
+    logger.flush()?;
+
+    let result = results.iter()
+        .find(|(id, _)| id == command_id)
+        .map(|(_, result)| result.clone())
+        .expect("Task result not found");
+    let outputs = executor.context.env.difference(&initial_env);
+
+    Ok(TaskOutcome { result, results, outputs })
+}
+
+/// Outcome of acquiring the job lock
+enum LockState {
+    /// The lock was acquired (or the job has no lock)
+    Acquired(Option<FileLock>),
+    /// The job should not be executed since another run holds the lock
+    Skipped,
+}
 
 /// Executor responsible for executing a flow.
 pub struct Executor<'a> {
     pub flow: &'a flow::Flow,
     pub context: ExecutionContext,
+    /// State of the run which is resumed
+    pub resumed: Option<RunState>,
+    /// Environment variables overriding the job env (set by the task running the job as a sub job)
+    pub overrides: Env,
 }
 
 impl<'a> Executor<'a> {
@@ -255,61 +869,380 @@ impl<'a> Executor<'a> {
         options: config::Options,
         flow: &'a flow::Flow,
     ) -> Result<Self> {
-        let cwd = resolve_cwd(&std::env::current_dir()?, flow.cwd.as_ref());
+        let run = RunInfo::new(&flow.id, &flow.name);
+
+        Ok(Executor {
+            flow,
+            context: ExecutionContext::new(options, run, std::env::current_dir()?),
+            resumed: None,
+            overrides: Env::default(),
+        })
+    }
+
+    /// Creates an executor continuing the run stored in the given run directory
+    pub fn resume(
+        options: config::Options,
+        flow: &'a flow::Flow,
+        state: RunState,
+        run_dir: PathBuf,
+    ) -> Result<Self> {
+        let mut context = ExecutionContext::new(options, state.run.clone(), std::env::current_dir()?);
+        context.log_dir = run_dir;
 
         Ok(Executor {
             flow,
-            context: ExecutionContext::new(options, cwd),
+            context,
+            resumed: Some(state),
+            overrides: Env::default(),
         })
     }
 
+    /// Creates an executor running the flow as a sub job within the run of the parent context.
+    /// The sub job inherits the options, env and secrets of its parent and logs to the same log dir.
+    pub fn nested(
+        flow: &'a flow::Flow,
+        parent: &ExecutionContext,
+        job_path: PathBuf,
+        cwd: PathBuf,
+        overrides: Env,
+    ) -> Self {
+        let run = RunInfo {
+            job_path: Some(job_path),
+            job_hash: None,
+            run_id: parent.run.run_id.clone(),
+            trigger: parent.run.trigger.clone(),
+            ..RunInfo::new(&flow.id, &flow.name)
+        };
+        let mut context = ExecutionContext::new(parent.options.clone(), run, cwd);
+        context.env = parent.env.clone();
+        context.log_dir = parent.log_dir.clone();
+        context.secret_env = parent.secret_env.clone();
+        context.secret_values = parent.secret_values.clone();
+        context.parents = parent.parents.iter().chain(parent.run.job_path.iter()).cloned().collect();
+        context.deadline = parent.deadline;
+
+        Executor {
+            flow,
+            context,
+            resumed: None,
+            overrides,
+        }
+    }
+
     /// Execute a whole flow
     pub fn execute(
         &mut self,
         logger: &mut Logger,
-    ) -> Result<()> {
-        // Setup dotenv
-        if self.context.options.system_env {
+    ) -> Result<JobResult> {
+        // Setup dotenv (sub jobs inherit the env of their parent instead)
+        if self.context.options.system_env && !self.context.is_nested() {
             self.context.env.extend(Env::from_system())
         }
-        if let Some(ref dotenv) = self.context.options.dotenv {
+        if let Some(dotenv) = self.context.options.dotenv.as_ref().filter(|_| !self.context.is_nested()) {
             let (env, _err) = Env::from_path(dotenv)
                 .map_err(|e| anyhow!("Failed to load dotenv file: {:?}. Error: {}", dotenv, e))?;
             // TODO: Handle errors in err
             self.context.env.extend(env)
         }
-        self.context.env.extend(self.flow.env.clone());
 
-        // Create log dir
-        self.context.log_dir = resolve_cwd(&self.context.cwd, self.context.options.log_dir.as_ref());
-        self.context.log_dir.push(
-            format!("{}_{}", self.flow.id, Local::now().format("%Y-%m-%dT%H:%M:%S"))
-        );
+        // Render the job env and cwd templates within the system env
+        let render = |field: &str, value: &str| template::render(value, &self.context)
+            .map_err(|e| anyhow!("Job \"{}\" failed to render field \"{}\": {}", self.flow.name, field, e));
+        let job_env = self.flow.env.iter()
+            .map(|(key, value)| Ok((key.clone(), render(&format!("env.{}", key), value)?)))
+            .collect::<Result<Env>>()?;
+        let job_cwd = self.flow.cwd.as_ref().map(|cwd| render("cwd", cwd)).transpose()?;
+        self.context.env.extend(job_env);
+        self.context.env.extend(self.overrides.clone());
+        self.context.cwd = resolve_cwd(&self.context.cwd, job_cwd.as_ref());
+
+        // Load the secrets, the secrets file is resolved relative to the job cwd
+        if let Some(secrets) = &self.flow.secrets {
+            if let Some(file) = &secrets.file {
+                let path = self.context.cwd.join(file);
+                let (env, errors) = Env::from_path(&path)
+                    .map_err(|e| anyhow!("Failed to load secrets file: {:?}. Error: {}", path, e))?;
+                if let Some(e) = errors.first() {
+                    return Err(anyhow!("Failed to load secrets file: {:?}. Error: {}", path, e));
+                }
+                self.context.secret_values.extend(env.iter().map(|(_, value)| value.clone()));
+                self.context.env.extend(env);
+            }
+            self.context.secret_env.extend(secrets.env.iter().cloned());
+        }
+
+        // Acquire the job lock, it is held until the job including its after job hooks has finished
+        let _lock = match self.acquire_lock(logger)? {
+            LockState::Acquired(lock) => lock,
+            LockState::Skipped => {
+                let result = JobResult::skipped();
+                self.record_history(&result, None)?;
+                return Ok(result);
+            }
+        };
+
+        // Create log dir (a resumed run and the sub jobs continue in the existing log dir)
+        if self.resumed.is_none() && !self.context.is_nested() {
+            self.context.log_dir = resolve_cwd(&self.context.cwd, self.context.options.log_dir.as_ref());
+            self.context.log_dir.push(&self.context.run.run_id);
+        }
         std::fs::create_dir_all(&self.context.log_dir)?;
 
         // Define global context variables
         self.context.env.insert(ENV_JOB_NAME.to_string(), self.flow.id.clone());
         self.context.env.insert(ENV_JOB_ID.to_string(), self.flow.id.clone());
 
+        // The job timeout starts once the lock is acquired (a sub job is limited by the timeout of its parent as well)
+        if let Some(timeout) = self.flow.timeout {
+            let deadline = Instant::now() + Duration::from(timeout);
+            self.context.deadline = Some(self.context.deadline.map_or(deadline, |parent| parent.min(deadline)));
+        }
+
+        // Switch the logger to the job level handlers
+        self.switch_to_job(logger)?;
+        logger.log_action(ActionJobStart { flow: self.flow, run: &self.context.run })?;
+
+        // Execute the before job hooks, the tasks and the after job hooks
+        let mut results = self.execute_routine(self.flow.iter_hook(Hook::BeforeJob), logger)?;
+        let outcomes = self.execute_tasks(logger)?;
+        for (command_id, outcome) in outcomes {
+            self.context.env.extend(outcome.outputs);
+            if outcome.result.is_failed() {
+                self.context.state = ExecutionState::Failed;
+            }
+            self.context.results.insert(command_id, outcome.result.clone());
+            self.context.previous = Some(outcome.result);
+            results.extend(outcome.results);
+        }
+        results.extend(self.execute_routine(self.flow.iter_hook(Hook::AfterJob), logger)?);
+
+        let mut result = JobResult::new(self.flow, &self.context.options, results);
+        // The names are masked before the summary table is laid out, so that its columns stay aligned
+        result.mask_secrets(&self.context.secrets());
+        self.switch_to_job(logger)?;
+        logger.flush()?;
+        // The results of a sub job are listed in the summary of its parent
+        if !self.context.is_nested() {
+            logger.log_action(ActionSummary { flow: self.flow, result: &result })?;
+        }
+        logger.log_action(ActionJobEnd { flow: self.flow, result: &result })?;
+        self.write_summaries(&result)?;
+        self.record_history(&result, Some(self.context.log_dir.clone()))?;
+        self.apply_retention(logger)?;
+
+        Ok(result)
+    }
+
+    /// Switches the logger context from the last command to the job itself
+    fn switch_to_job(&mut self, logger: &mut Logger) -> Result<()> {
+        self.context.current = None;
+        self.context.focus = None;
+        logger.switch(&self.context)
+    }
+
+    /// Returns the root log dir the run directories are created in
+    fn log_root(&self) -> PathBuf {
+        // A resumed run may have been started with a different log dir
+        match (&self.resumed, self.context.log_dir.parent()) {
+            (Some(_), Some(parent)) => parent.to_path_buf(),
+            _ => resolve_cwd(&self.context.cwd, self.context.options.log_dir.as_ref()),
+        }
+    }
+
+    /// Deletes or compresses the previous runs of the job according to the retention policy
+    fn apply_retention(&self, logger: &mut Logger) -> Result<()> {
+        let policy = match &self.context.options.retention {
+            Some(policy) if !self.context.options.dry_run && !self.context.is_nested() => policy,
+            _ => return Ok(()),
+        };
+        let runs = retention::find_runs(&self.log_root(), &self.flow.id)?;
+        let now = Local::now().naive_local();
+        for (run, action) in retention::plan(policy, &runs, now, Some(&self.context.run.run_id)) {
+            logger.log_action(ActionRunPruned { path: &run.path, action, dry_run: false })?;
+            retention::apply(run, action)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the configured run summaries (to the run log dir unless a path is given)
+    fn write_summaries(&self, result: &JobResult) -> Result<()> {
+        if self.context.options.dry_run || self.context.is_nested() || self.context.options.summary.is_empty() {
+            return Ok(());
+        }
+        let mut record = RunRecord::new(self.flow, &self.context.run, Some(self.context.log_dir.clone()), result);
+        record.mask_secrets(&self.context.secrets());
+        for config::Summary { format, output } in &self.context.options.summary {
+            let output = match output {
+                Some(output) => template::render(output, &self.context)
+                    .map_err(|e| anyhow!("Summary \"{:?}\" failed to render field \"output\": {}", format, e))?,
+                None => format.default_file().to_string(),
+            };
+            summary::write(*format, &record, &resolve_cwd(&self.context.log_dir, Some(&output)))?;
+        }
+        Ok(())
+    }
+
+    /// Appends the run to the history stored in the root log dir (sub jobs are a part of their parent run)
+    fn record_history(&self, result: &JobResult, log_dir: Option<PathBuf>) -> Result<()> {
+        if self.context.options.dry_run || self.context.is_nested() {
+            return Ok(());
+        }
+        let mut record = RunRecord::new(self.flow, &self.context.run, log_dir, result);
+        record.mask_secrets(&self.context.secrets());
+        History::in_dir(&self.log_root()).append(&record)
+    }
+
+    /// Acquires the job lock if the job is configured to be locked
+    fn acquire_lock(&self, logger: &mut Logger) -> Result<LockState> {
+        let lock = match &self.flow.lock {
+            Some(lock) => lock,
+            None => return Ok(LockState::Acquired(None)),
+        };
+        let path = match &lock.path {
+            Some(path) => resolve_cwd(&self.context.cwd, Some(path)),
+            None => self.context.options.temp_path.join(format!("nauman-{}.lock", self.flow.id)),
+        };
+
+        if let Some(file_lock) = FileLock::try_acquire(&path)? {
+            return Ok(LockState::Acquired(Some(file_lock)));
+        }
+        if lock.behavior != LockBehavior::Fail {
+            logger.log_action(ActionJobLocked { flow: self.flow, path: &path, behavior: lock.behavior })?;
+        }
+        match lock.behavior {
+            LockBehavior::Skip => Ok(LockState::Skipped),
+            LockBehavior::Fail => Err(anyhow!("Job is already running (lock file: {})", path.display())),
+            LockBehavior::Wait => match FileLock::acquire(&path, lock.timeout.map(Duration::from))? {
+                Some(file_lock) => Ok(LockState::Acquired(Some(file_lock))),
+                None => Err(anyhow!(
+                    "Timed out after {} waiting for the lock (lock file: {})",
+                    lock.timeout.expect("Lock timeout not set"), path.display()
+                )),
+            },
+        }
+    }
+
+    /// Stores the state of the run in the log dir, so that it can be resumed later
+    fn save_state(&self, completed: &[(CommandId, TaskOutcome)]) -> Result<()> {
+        // The state of a sub job is not stored, since it is rerun as a whole on resume
+        if self.context.options.dry_run || self.context.is_nested() {
+            return Ok(());
+        }
+        let mut state = RunState::new(self.context.run.clone(), self.context.options.clone(), completed);
+        state.mask_secrets(&self.context.secret_env, &self.context.secrets());
+        state.save(&self.context.log_dir)
+    }
+
+    /// Execute the main routine tasks in the order of their dependencies.
+    /// Independent tasks are executed in parallel (up to `max_parallel` at the same time).
+    /// Returns the task outcomes in the order of completion.
+    fn execute_tasks(&self, logger: &mut Logger) -> Result<Vec<(CommandId, TaskOutcome)>> {
+        let max_parallel = self.context.options.max_parallel.max(1);
+        let mut completed: Vec<(CommandId, TaskOutcome)> = Vec::new();
+        if let Some(state) = &self.resumed {
+            completed = state.restore(self.flow);
+            logger.log_action(ActionJobResumed {
+                flow: self.flow,
+                run_dir: &self.context.log_dir,
+                restored: completed.len(),
+                total: state.tasks.len(),
+            })?;
+        }
+        let mut pending: Vec<CommandId> = self.flow.tasks().iter()
+            .filter(|command_id| !completed.iter().any(|(id, _)| id == *command_id))
+            .cloned()
+            .collect();
+        self.save_state(&completed)?;
+
+        thread::scope(|scope| {
+            let (sender, receiver) = unbounded();
+            let mut running = 0;
+
+            loop {
+                // Start the tasks for which all dependencies have completed
+                while running < max_parallel {
+                    let ready = pending.iter().position(|command_id| {
+                        let command = self.flow.command(command_id).expect("Command not found");
+                        command.needs.iter().all(|need| completed.iter().any(|(id, _)| id == need))
+                    });
+                    let command_id = match ready {
+                        Some(index) => pending.remove(index),
+                        None => break,
+                    };
+                    if !self.flow.is_selected(&command_id) {
+                        let outcome = TaskOutcome::deselected(&command_id);
+                        completed.push((command_id, outcome));
+                        self.save_state(&completed)?;
+                        continue;
+                    }
+                    let context = self.task_context(&command_id, &completed);
+
+                    if max_parallel == 1 {
+                        let outcome = execute_task(self.flow, context, &command_id, logger)?;
+                        completed.push((command_id, outcome));
+                        self.save_state(&completed)?;
+                    } else {
+                        let flow = self.flow;
+                        let sender = sender.clone();
+                        let mut logger = logger.fork(Some(command_id.clone()));
+                        scope.spawn(move || {
+                            let outcome = execute_task(flow, context, &command_id, &mut logger);
+                            sender.send((command_id, outcome)).expect("Failed to send task outcome");
+                        });
+                        running += 1;
+                    }
+                }
+
+                if running == 0 {
+                    break;
+                }
+                let (command_id, outcome) = receiver.recv()?;
+                running -= 1;
+                completed.push((command_id, outcome?));
+                self.save_state(&completed)?;
+            }
+
+            if !pending.is_empty() {
+                return Err(anyhow!("Tasks with unresolvable dependencies: {}", pending.join(", ")));
+            }
+            Ok(completed)
+        })
+    }
+
+    /// Builds execution context for a task given the already completed tasks
+    fn task_context(&self, command_id: &CommandId, completed: &[(CommandId, TaskOutcome)]) -> ExecutionContext {
+        let command = self.flow.command(command_id).expect("Command not found");
+        let upstream = self.flow.upstream(command_id);
+
+        let mut context = self.context.clone();
+        for (id, outcome) in completed.iter().filter(|(id, _)| upstream.contains(id)) {
+            context.env.extend(outcome.outputs.clone());
+            if outcome.result.is_failed() {
+                context.state = ExecutionState::Failed;
+            }
+            context.results.insert(id.clone(), outcome.result.clone());
+            if command.needs.last() == Some(id) {
+                context.previous = Some(outcome.result.clone());
+            }
+        }
+        context
+    }
+
+    /// Execute all the commands yielded by the flow iterator
+    fn execute_routine(
+        &mut self,
+        mut flow_iter: FlowIterator,
+        logger: &mut Logger,
+    ) -> Result<Vec<(CommandId, ExecutionResult)>> {
         // Loop through all the commands and store the results
         let mut results = Vec::new();
-        let mut flow_iter = self.flow.iter();
         while let Some((command_id, command, focus_id)) = flow_iter.next() {
             let result = self.execute_step(&command_id, &command, focus_id.as_ref(), logger)?;
             flow_iter.push_result(&command_id, &result);
 
             results.push((command_id.clone(), result));
         }
-
-        let summary = ActionSummary {
-            flow: self.flow,
-            results: &results,
-        };
-
-        logger.flush()?;
-        logger.log_action(summary)?;
-
-        Ok(())
+        Ok(results)
     }
 
     /// Execute a single command
@@ -323,20 +1256,51 @@ impl<'a> Executor<'a> {
         // Set up the context for the current command
         self.context.current = Some((command_id.clone(), command.clone()));
         self.context.focus = focus_id.cloned();
-        self.context.will_execute = match command.policy {
+        let permitted = match command.policy {
             ExecutionPolicy::NoPriorFailed => self.context.state != ExecutionState::Failed,
             ExecutionPolicy::PriorSuccess => self.context.previous.as_ref().map(|r| r.is_success()).unwrap_or(true),
             ExecutionPolicy::Always => true
         };
+        // The remaining tasks are not started once the job timeout has expired
+        let expired = self.context.is_expired(command);
+        self.context.will_execute = !expired && permitted && command.condition.as_ref()
+            .map(|condition| condition.evaluate(&self.context))
+            .unwrap_or(true);
+
+        // Render the templates right before execution, so that the outputs of prior tasks are available.
+        // A template which can not be rendered (e.g. an unset variable) fails the task.
+        let rendered;
+        let mut render_error = None;
+        let command = if self.context.will_execute {
+            match command.render(&self.context) {
+                Ok(command) => {
+                    rendered = command;
+                    self.context.current = Some((command_id.clone(), rendered.clone()));
+                    &rendered
+                }
+                Err(e) => {
+                    render_error = Some(e);
+                    command
+                }
+            }
+        } else {
+            command
+        };
 
         // Switch logger context to the current command
         logger.switch(&self.context)?;
 
         // Execute the command if possible
-        let result = if self.context.will_execute {
-            // Announce command to execute
-            logger.log_action(ActionCommandStart { command })?;
-
+        let result = if let Some(e) = render_error {
+            logger.log_action(ActionCommandStart { command, attempt: 1, max_attempts: 1 })?;
+            logger.mut_output().write_stream(InputStream::Stderr, format!("{}\n", e).as_bytes())?;
+            ExecutionResult {
+                exit_code: 2,
+                attempts: 1,
+                duration: Some(Duration::ZERO),
+                ..ExecutionResult::new(command_id.clone(), focus_id.cloned())
+            }
+        } else if self.context.will_execute {
             // Prepare context
             // TODO: should this be moved to context preparation?
             if let Some(previous) = self.context.previous.as_ref() {
@@ -348,45 +1312,87 @@ impl<'a> Executor<'a> {
             self.context.env.insert(ENV_TASK_NAME.to_string(), command.name.clone());
             self.context.env.insert(ENV_TASK_ID.to_string(), command_id.clone());
 
-            // Create temporary output file
-            with_tempfile(&self.context.options.temp_path.clone(), |output_file| {
-                self.context.env.insert(ENV_OUTPUT_FILE.to_string(), output_file.to_str().unwrap().to_string());
+            // Execute the command until it succeeds or runs out of attempts
+            let max_attempts = command.retry.as_ref().map(|r| r.attempts.max(1)).unwrap_or(1);
+            let now = Instant::now();
+            let mut attempt = 1;
+            loop {
+                // Announce command to execute
+                logger.log_action(ActionCommandStart { command, attempt, max_attempts })?;
 
-                // Execute the actual command
-                let result = execute_command(command, &mut self.context, logger)?;
+                let mut result = self.execute_attempt(command, attempt, logger)?;
+                result.attempts = attempt;
+                result.duration = Some(now.elapsed());
 
-                // Load the outputs
-                if output_file.exists() {
-                    let (env, _err) = Env::from_path(output_file)
-                        .map_err(|e| anyhow!("Failed to load output file: {:?}. Error: {}", output_file, e))?;
-                    // TODO: Handle errors in err
-                    self.context.env.extend(env);
+                match &command.retry {
+                    Some(retry) if !result.is_success() && attempt < max_attempts && retry.is_retryable(result.exit_code)
+                        && !self.context.is_expired(command) => {
+                        let delay = retry.delay(attempt);
+                        logger.log_action(ActionCommandRetry { command, result: &result, max_attempts, delay })?;
+                        std::thread::sleep(delay);
+                        attempt += 1;
+                    }
+                    _ => break result,
                 }
-
-                Ok(result)
-            })?
+            }
+        } else if expired {
+            ExecutionResult {
+                exit_code: -1,
+                timed_out: true,
+                ..ExecutionResult::new(command_id.clone(), focus_id.cloned())
+            }
         } else {
             ExecutionResult {
-                command_id: command_id.clone(),
-                focus_id: focus_id.cloned(),
-                exit_code: 0,
-                aborted: true,
-                duration: None,
+                aborted: !permitted,
+                skipped: permitted,
+                ..ExecutionResult::new(command_id.clone(), focus_id.cloned())
             }
         };
 
+        let result = ExecutionResult { name: Some(command.name.clone()), is_hook: command.is_hook, ..result };
+
         // Announce command result
         logger.log_action(ActionCommandEnd { command, result: &result })?;
 
         // Only main command state is stored
         if !command.is_hook {
-            if !result.is_success() && !result.is_aborted() {
+            if result.is_failed() {
                 self.context.state = ExecutionState::Failed;
             }
 
+            self.context.results.insert(command_id.clone(), result.clone());
             self.context.previous = Some(result.clone());
         }
         Ok(result)
     }
+
+    /// Execute a single attempt of a command
+    fn execute_attempt(
+        &mut self,
+        command: &flow::Command,
+        attempt: u32,
+        logger: &mut Logger,
+    ) -> Result<ExecutionResult> {
+        self.context.env.insert(ENV_ATTEMPT.to_string(), attempt.to_string());
+
+        // Create temporary output file
+        with_tempfile(&self.context.options.temp_path.clone(), |output_file| {
+            self.context.env.insert(ENV_OUTPUT_FILE.to_string(), output_file.to_str().unwrap().to_string());
+
+            // Execute the actual command
+            let mut result = execute_command(command, &mut self.context, logger)?;
+
+            // Load the outputs (in addition to the ones set by the handler itself)
+            if output_file.exists() {
+                let (env, _err) = Env::from_path(output_file)
+                    .map_err(|e| anyhow!("Failed to load output file: {:?}. Error: {}", output_file, e))?;
+                // TODO: Handle errors in err
+                result.outputs.extend(env);
+            }
+            self.context.env.extend(result.outputs.clone());
+
+            Ok(result)
+        })
+    }
 }
 
@@ -0,0 +1,446 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use anyhow::{anyhow, Result};
+use crate::execution::ExecutionResult;
+
+/// Value an expression evaluates to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl Value {
+    /// Whether the value is considered true in a boolean context
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(value) => *value,
+            Value::Number(value) => *value != 0.0,
+            Value::String(value) => !value.is_empty(),
+        }
+    }
+
+    /// Returns the numeric representation of the value if there is one
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(value) => Some(*value),
+            Value::String(value) => value.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Compares two values. Numbers (or numeric strings) are compared numerically,
+    /// everything else is compared by its string representation.
+    fn compare(&self, other: &Value) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Value::Null, Value::Null) => Some(std::cmp::Ordering::Equal),
+            (Value::Null, _) | (_, Value::Null) => None,
+            (Value::Bool(a), Value::Bool(b)) => a.partial_cmp(b),
+            _ => match (self.as_number(), other.as_number()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => self.to_string().partial_cmp(&other.to_string()),
+            }
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, ""),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Field of a task result which can be referenced in an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskField {
+    Status,
+    ExitCode,
+    Duration,
+    Attempts,
+    Output(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    /// A constant value
+    Literal(Value),
+    /// An environment variable
+    Env(String),
+    /// A field of a prior task result
+    Task(String, TaskField),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Compare(CompareOp, Box<Expression>, Box<Expression>),
+}
+
+/// Provides the values referenced in an expression
+pub trait Scope {
+    /// Returns the value of an environment variable
+    fn env(&self, name: &str) -> Option<String>;
+
+    /// Returns the result of a task if it has been executed
+    fn task(&self, id: &str) -> Option<&ExecutionResult>;
+
+    /// Returns the value of a built-in variable such as `job.id` or `run.date`
+    fn builtin(&self, _name: &str) -> Option<String> {
+        None
+    }
+}
+
+impl Expression {
+    /// Evaluates the expression within the given scope
+    pub fn evaluate(&self, scope: &impl Scope) -> Value {
+        match self {
+            Expression::Literal(value) => value.clone(),
+            Expression::Env(name) => scope.env(name).map(Value::String).unwrap_or(Value::Null),
+            Expression::Task(id, field) => match scope.task(id) {
+                None => Value::Null,
+                Some(result) => match field {
+                    TaskField::Status => Value::String(result.status().to_string()),
+                    TaskField::ExitCode => Value::Number(result.exit_code as f64),
+                    TaskField::Duration => result.duration
+                        .map(|d| Value::Number(d.as_secs_f64()))
+                        .unwrap_or(Value::Null),
+                    TaskField::Attempts => Value::Number(result.attempts as f64),
+                    TaskField::Output(name) => result.outputs.get(name)
+                        .map(|value| Value::String(value.clone()))
+                        .unwrap_or(Value::Null),
+                }
+            },
+            Expression::Not(inner) => Value::Bool(!inner.evaluate(scope).is_truthy()),
+            Expression::And(left, right) => {
+                Value::Bool(left.evaluate(scope).is_truthy() && right.evaluate(scope).is_truthy())
+            }
+            Expression::Or(left, right) => {
+                Value::Bool(left.evaluate(scope).is_truthy() || right.evaluate(scope).is_truthy())
+            }
+            Expression::Compare(op, left, right) => {
+                let ordering = left.evaluate(scope).compare(&right.evaluate(scope));
+                Value::Bool(match op {
+                    CompareOp::Eq => ordering == Some(std::cmp::Ordering::Equal),
+                    CompareOp::Ne => ordering != Some(std::cmp::Ordering::Equal),
+                    CompareOp::Lt => ordering == Some(std::cmp::Ordering::Less),
+                    CompareOp::Le => matches!(ordering, Some(std::cmp::Ordering::Less | std::cmp::Ordering::Equal)),
+                    CompareOp::Gt => ordering == Some(std::cmp::Ordering::Greater),
+                    CompareOp::Ge => matches!(ordering, Some(std::cmp::Ordering::Greater | std::cmp::Ordering::Equal)),
+                })
+            }
+        }
+    }
+
+    /// Returns ids of all the tasks referenced in the expression
+    pub fn task_references(&self) -> Vec<&String> {
+        match self {
+            Expression::Literal(_) | Expression::Env(_) => Vec::new(),
+            Expression::Task(id, _) => vec![id],
+            Expression::Not(inner) => inner.task_references(),
+            Expression::And(left, right)
+            | Expression::Or(left, right)
+            | Expression::Compare(_, left, right) => {
+                let mut result = left.task_references();
+                result.extend(right.task_references());
+                result
+            }
+        }
+    }
+}
+
+/// A parsed condition together with its source text
+#[derive(Debug, Clone)]
+pub struct Condition {
+    pub source: String,
+    pub expression: Expression,
+}
+
+impl Condition {
+    pub fn evaluate(&self, scope: &impl Scope) -> bool {
+        self.expression.evaluate(scope).is_truthy()
+    }
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = Parser::new(s)?.parse()
+            .map_err(|e| anyhow!("Invalid condition \"{}\": {}", s, e))?;
+        Ok(Condition { source: s.to_string(), expression })
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    String(String),
+    Number(f64),
+    Word(String),
+    Variable(String),
+    Operator(&'static str),
+}
+
+const OPERATORS: [&str; 11] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")"];
+
+/// Splits the expression source into tokens
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-' || c == '.';
+
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..].iter().take(2).collect();
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            let end = chars[i + 1..].iter().position(|x| *x == c)
+                .ok_or_else(|| anyhow!("Unterminated string literal"))?;
+            tokens.push(Token::String(chars[i + 1..i + 1 + end].iter().collect()));
+            i += end + 2;
+        } else if c == '$' {
+            let (name, len) = if chars.get(i + 1) == Some(&'{') {
+                let end = chars[i + 2..].iter().position(|x| *x == '}')
+                    .ok_or_else(|| anyhow!("Unterminated variable reference"))?;
+                (chars[i + 2..i + 2 + end].iter().collect::<String>(), end + 3)
+            } else {
+                let name: String = chars[i + 1..].iter()
+                    .take_while(|x| x.is_alphanumeric() || **x == '_').collect();
+                let len = name.chars().count() + 1;
+                (name, len)
+            };
+            if name.is_empty() {
+                return Err(anyhow!("Empty variable reference"));
+            }
+            tokens.push(Token::Variable(name));
+            i += len;
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).map(|x| x.is_ascii_digit()).unwrap_or(false)) {
+            let text: String = chars[i..].iter().enumerate()
+                .take_while(|(j, x)| x.is_ascii_digit() || **x == '.' || (*j == 0 && **x == '-'))
+                .map(|(_, x)| *x).collect();
+            // Identifiers such as generated task ids may start with a digit
+            if chars.get(i + text.len()).map(|x| is_word_char(*x)).unwrap_or(false) {
+                let word: String = chars[i..].iter().take_while(|x| is_word_char(**x)).collect();
+                i += word.chars().count();
+                tokens.push(Token::Word(word));
+            } else {
+                tokens.push(Token::Number(text.parse().map_err(|_| anyhow!("Invalid number {}", text))?));
+                i += text.len();
+            }
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Operator(op));
+            i += op.len();
+        } else if is_word_char(c) {
+            let word: String = chars[i..].iter().take_while(|x| is_word_char(**x)).collect();
+            i += word.chars().count();
+            tokens.push(Token::Word(word));
+        } else {
+            return Err(anyhow!("Unexpected character '{}'", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser for the condition expressions
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Self> {
+        let tokens = tokenize(source)
+            .map_err(|e| anyhow!("Invalid condition \"{}\": {}", source, e))?;
+        Ok(Parser { tokens, position: 0 })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn accept(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Operator(o)) if *o == op) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse(mut self) -> Result<Expression> {
+        let expression = self.parse_or()?;
+        match self.peek() {
+            None => Ok(expression),
+            Some(token) => Err(anyhow!("Unexpected token {:?}", token)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expression> {
+        let mut left = self.parse_and()?;
+        while self.accept("||") {
+            left = Expression::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression> {
+        let mut left = self.parse_not()?;
+        while self.accept("&&") {
+            left = Expression::And(Box::new(left), Box::new(self.parse_not()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expression> {
+        if self.accept("!") {
+            Ok(Expression::Not(Box::new(self.parse_not()?)))
+        } else {
+            self.parse_comparison()
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression> {
+        let left = self.parse_primary()?;
+        let op = match self.peek() {
+            Some(Token::Operator("==")) => CompareOp::Eq,
+            Some(Token::Operator("!=")) => CompareOp::Ne,
+            Some(Token::Operator("<")) => CompareOp::Lt,
+            Some(Token::Operator("<=")) => CompareOp::Le,
+            Some(Token::Operator(">")) => CompareOp::Gt,
+            Some(Token::Operator(">=")) => CompareOp::Ge,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        let right = self.parse_primary()?;
+        Ok(Expression::Compare(op, Box::new(left), Box::new(right)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression> {
+        match self.next() {
+            Some(Token::Operator("(")) => {
+                let expression = self.parse_or()?;
+                if !self.accept(")") {
+                    return Err(anyhow!("Expected ')'"));
+                }
+                Ok(expression)
+            }
+            Some(Token::String(value)) => Ok(Expression::Literal(Value::String(value))),
+            Some(Token::Number(value)) => Ok(Expression::Literal(Value::Number(value))),
+            Some(Token::Variable(name)) => Ok(Expression::Env(name)),
+            Some(Token::Word(word)) => parse_word(&word),
+            Some(token) => Err(anyhow!("Unexpected token {:?}", token)),
+            None => Err(anyhow!("Unexpected end of expression")),
+        }
+    }
+}
+
+/// Parses a bare word, which is either a keyword, a reference or a plain string
+fn parse_word(word: &str) -> Result<Expression> {
+    let parts: Vec<&str> = word.split('.').collect();
+    Ok(match parts.as_slice() {
+        ["true"] => Expression::Literal(Value::Bool(true)),
+        ["false"] => Expression::Literal(Value::Bool(false)),
+        ["null"] => Expression::Literal(Value::Null),
+        ["env", name] => Expression::Env(name.to_string()),
+        ["tasks", id, field] => Expression::Task(id.to_string(), match *field {
+            "status" => TaskField::Status,
+            "exit_code" => TaskField::ExitCode,
+            "duration" => TaskField::Duration,
+            "attempts" => TaskField::Attempts,
+            _ => return Err(anyhow!("Unknown task field \"{}\"", field)),
+        }),
+        ["tasks", id, "outputs", name] => Expression::Task(id.to_string(), TaskField::Output(name.to_string())),
+        [_] => Expression::Literal(Value::String(word.to_string())),
+        _ => return Err(anyhow!("Unknown reference \"{}\"", word)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use test_case::test_case;
+    use crate::execution::ExecutionResult;
+    use crate::expression::{Condition, Scope};
+
+    struct TestScope {
+        env: HashMap<String, String>,
+        tasks: HashMap<String, ExecutionResult>,
+    }
+
+    impl Scope for TestScope {
+        fn env(&self, name: &str) -> Option<String> {
+            self.env.get(name).cloned()
+        }
+
+        fn task(&self, id: &str) -> Option<&ExecutionResult> {
+            self.tasks.get(id)
+        }
+    }
+
+    fn scope() -> TestScope {
+        let mut fetch = ExecutionResult::new("fetch".to_string(), None);
+        fetch.exit_code = 2;
+        fetch.outputs.insert("COUNT".to_string(), "10".to_string());
+        TestScope {
+            env: [("DEPLOY_ENV", "prod"), ("RETRIES", "3")].iter()
+                .map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            tasks: [("fetch".to_string(), fetch)].into_iter().collect(),
+        }
+    }
+
+    #[test_case("$DEPLOY_ENV == prod", true)]
+    #[test_case("${DEPLOY_ENV} == 'staging'", false)]
+    #[test_case("env.DEPLOY_ENV != \"prod\"", false)]
+    #[test_case("$RETRIES > 2 && $RETRIES <= 3", true)]
+    #[test_case("$MISSING", false)]
+    #[test_case("!$MISSING || false", true)]
+    #[test_case("tasks.fetch.status == failure && tasks.fetch.exit_code == 2", true)]
+    #[test_case("tasks.fetch.outputs.COUNT >= 9", true)]
+    #[test_case("tasks.other.status == success", false)]
+    #[test_case("(true || false) && !(1 > 2)", true)]
+    fn test_evaluate(source: &str, expected: bool) {
+        let condition = Condition::from_str(source).unwrap();
+        assert_eq!(condition.evaluate(&scope()), expected);
+    }
+
+    #[test_case("$A ==" ; "missing operand")]
+    #[test_case("(true" ; "unclosed parenthesis")]
+    #[test_case("'text" ; "unterminated string")]
+    #[test_case("tasks.fetch.unknown" ; "unknown field")]
+    #[test_case("true false" ; "trailing token")]
+    fn test_parse_invalid(source: &str) {
+        assert!(Condition::from_str(source).is_err());
+    }
+}
@@ -1,14 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use crate::{
-    common::Env,
+    common::{Env, HumanDuration, LocatedError},
     config,
     config::{Hook}
 };
-use crate::config::{ExecutionPolicy, TaskHandler};
+use crate::config::{ExecutionPolicy, Retry, TaskHandler};
 use crate::execution::ExecutionResult;
+use crate::expression::{Condition, Scope};
+use crate::template::{self, Template};
+use crate::script::is_executable;
+use crate::utils::{glob_match, resolve_cwd};
+use std::str::FromStr;
 
 pub type CommandId = String;
 pub type RoutineId = String;
@@ -55,6 +63,129 @@ pub struct Command {
     pub hooks: Hooks,
     /// The command's execution policy
     pub policy: ExecutionPolicy,
+    /// Maximum time the command is allowed to run
+    pub timeout: Option<HumanDuration>,
+    /// The command's retry policy
+    pub retry: Option<Retry>,
+    /// Main commands which need to complete before this command can be executed
+    pub needs: Vec<CommandId>,
+    /// Condition which must hold for the command to be executed
+    pub condition: Option<Condition>,
+}
+
+impl Command {
+    /// Returns the fields of the command which may contain templates
+    pub fn templated_fields(&self) -> Vec<(String, &String)> {
+        let mut fields = vec![("name".to_string(), &self.name)];
+        fields.extend(self.cwd.as_ref().map(|cwd| ("cwd".to_string(), cwd)));
+        fields.extend(self.env.iter().map(|(key, value)| (format!("env.{}", key), value)));
+        match &self.handler {
+            TaskHandler::Shell(shell) => fields.push(("run".to_string(), &shell.run)),
+            TaskHandler::Http(http) => {
+                let request = &http.http;
+                fields.push(("http.url".to_string(), &request.url));
+                fields.extend(request.headers.iter().map(|(key, value)| (format!("http.headers.{}", key), value)));
+                fields.extend(request.body.as_ref().map(|body| ("http.body".to_string(), body)));
+                if let Some(json) = &request.json {
+                    json_strings(json, "http.json", &mut fields);
+                }
+            }
+            TaskHandler::Script(script) => {
+                fields.push(("script".to_string(), &script.script));
+                fields.extend(script.args.iter().enumerate().map(|(index, arg)| (format!("args.{}", index), arg)));
+            }
+            TaskHandler::Exec(exec) => {
+                fields.extend(exec.command.iter().enumerate().map(|(index, arg)| (format!("command.{}", index), arg)));
+            }
+            TaskHandler::Job(job) => fields.push(("job".to_string(), &job.job)),
+        }
+        fields
+    }
+
+    /// Returns a copy of the command with all its templates rendered within the given scope
+    pub fn render(&self, scope: &impl Scope) -> Result<Command> {
+        let render = |field: &str, value: &str| template::render(value, scope)
+            .map_err(|e| anyhow!("Task \"{}\" failed to render field \"{}\": {}", self.name, field, e));
+
+        let mut command = self.clone();
+        command.name = render("name", &self.name)?;
+        command.cwd = self.cwd.as_ref().map(|cwd| render("cwd", cwd)).transpose()?;
+        command.env = self.env.iter()
+            .map(|(key, value)| Ok((key.clone(), render(&format!("env.{}", key), value)?)))
+            .collect::<Result<Env>>()?;
+        match &mut command.handler {
+            TaskHandler::Shell(shell) => shell.run = render("run", &shell.run)?,
+            TaskHandler::Http(http) => {
+                let request = &mut http.http;
+                request.url = render("http.url", &request.url)?;
+                for (key, value) in request.headers.iter_mut() {
+                    *value = render(&format!("http.headers.{}", key), value)?;
+                }
+                request.body = request.body.as_ref().map(|body| render("http.body", body)).transpose()?;
+                if let Some(json) = request.json.as_mut() {
+                    render_json_strings(json, "http.json", &render)?;
+                }
+            }
+            TaskHandler::Script(script) => {
+                script.script = render("script", &script.script)?;
+                for (index, arg) in script.args.iter_mut().enumerate() {
+                    *arg = render(&format!("args.{}", index), arg)?;
+                }
+            }
+            TaskHandler::Exec(exec) => {
+                for (index, arg) in exec.command.iter_mut().enumerate() {
+                    *arg = render(&format!("command.{}", index), arg)?;
+                }
+            }
+            TaskHandler::Job(job) => job.job = render("job", &job.job)?,
+        }
+        Ok(command)
+    }
+}
+
+/// Collects the string values within a json value together with their field paths
+fn json_strings<'a>(value: &'a serde_json::Value, path: &str, fields: &mut Vec<(String, &'a String)>) {
+    match value {
+        serde_json::Value::String(text) => fields.push((path.to_string(), text)),
+        serde_json::Value::Array(items) => items.iter().enumerate()
+            .for_each(|(index, item)| json_strings(item, &format!("{}.{}", path, index), fields)),
+        serde_json::Value::Object(items) => items.iter()
+            .for_each(|(key, item)| json_strings(item, &format!("{}.{}", path, key), fields)),
+        _ => {}
+    }
+}
+
+/// Renders the templates within the string values of a json value
+fn render_json_strings(
+    value: &mut serde_json::Value,
+    path: &str,
+    render: &impl Fn(&str, &str) -> Result<String>,
+) -> Result<()> {
+    match value {
+        serde_json::Value::String(text) => *text = render(path, text)?,
+        serde_json::Value::Array(items) => for (index, item) in items.iter_mut().enumerate() {
+            render_json_strings(item, &format!("{}.{}", path, index), render)?;
+        },
+        serde_json::Value::Object(items) => for (key, item) in items.iter_mut() {
+            render_json_strings(item, &format!("{}.{}", path, key), render)?;
+        },
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns all the commands the given command (transitively) depends on
+fn upstream(dependencies: &Dependencies, command_id: &CommandId) -> HashSet<CommandId> {
+    let mut result = HashSet::new();
+    let mut stack = vec![command_id];
+    while let Some(current) = stack.pop() {
+        for need in &dependencies.get(current).expect("Command not found").needs {
+            if result.insert(need.clone()) {
+                stack.push(need);
+            }
+        }
+    }
+    result
 }
 
 #[derive(Debug, Clone)]
@@ -82,6 +213,16 @@ pub struct Flow {
     pub env: Env,
     /// Working directory to execute the job in.
     pub cwd: Option<String>,
+    /// Lock preventing concurrent runs of the job
+    pub lock: Option<config::Lock>,
+    /// Maximum duration of the job tasks
+    pub timeout: Option<HumanDuration>,
+    /// Secrets masked in the output
+    pub secrets: Option<config::Secrets>,
+    /// Log handlers of the job (including the included ones)
+    pub logging: config::LogHandlers,
+    /// Main commands which are not selected for execution
+    pub deselected: HashSet<CommandId>,
 }
 
 impl Flow {
@@ -90,14 +231,109 @@ impl Flow {
         FlowBuilder::new().parse_flow(job)
     }
 
+    /// Creates a new flow from the job configuration, failing with all the errors found (e.g. for validation)
+    pub fn parse_all(job: &config::Job) -> std::result::Result<Self, Vec<anyhow::Error>> {
+        FlowBuilder::new().parse_flow_all(job)
+    }
+
+    /// Parses a job whose relative working directory is resolved from the given directory (e.g. a sub job)
+    pub fn parse_in(job: &config::Job, cwd: &Path) -> Result<Self> {
+        FlowBuilder { cwd: Some(cwd.to_path_buf()), ..FlowBuilder::new() }.parse_flow(job)
+    }
+
     /// Get command by id
     pub fn command(&self, command_id: &CommandId) -> Option<&Command> {
         self.dependencies.get(command_id)
     }
 
-    /// Iterates through flow's commands including hook logic
-    pub fn iter(&self) -> FlowIterator {
-        FlowIterator::new(self)
+    /// Returns ids of the main routine commands in the order of definition
+    pub fn tasks(&self) -> &Vec<CommandId> {
+        &self.routines.get(MAIN_ROUTINE_NAME).expect("Main routine not found").commands
+    }
+
+    /// Returns all the commands the given command (transitively) depends on
+    pub fn upstream(&self, command_id: &CommandId) -> HashSet<CommandId> {
+        upstream(&self.dependencies, command_id)
+    }
+
+    /// Whether the main command is selected for execution
+    pub fn is_selected(&self, command_id: &CommandId) -> bool {
+        !self.deselected.contains(command_id)
+    }
+
+    /// Deselects the main routine tasks which are not matched by the selection
+    pub fn select(&mut self, selection: &TaskSelection) -> Result<()> {
+        let selected = selection.apply(self.tasks())?;
+        self.deselected = self.tasks().iter()
+            .filter(|command_id| !selected.contains(command_id))
+            .cloned()
+            .collect();
+        Ok(())
+    }
+
+    /// Removes all the global and task specific hooks
+    pub fn strip_hooks(&mut self) {
+        self.hooks.clear();
+        for command in self.dependencies.values_mut() {
+            command.hooks.clear();
+        }
+    }
+
+    /// Iterates through a single main routine command including its hooks
+    pub fn iter_task(&self, command_id: &CommandId) -> FlowIterator<'_> {
+        FlowIterator::new(self, vec![command_id.clone()], false)
+    }
+
+    /// Iterates through commands of a global hook
+    pub fn iter_hook(&self, hook: Hook) -> FlowIterator<'_> {
+        let commands = self.hooks.get(&hook)
+            .map(|routine_id| self.routines.get(routine_id).expect("Routine not found").commands.clone())
+            .unwrap_or_default();
+        FlowIterator::new(self, commands, true)
+    }
+}
+
+/// Selection of the main routine tasks to execute by their ids or glob patterns
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaskSelection {
+    /// Only execute the matching tasks
+    pub only: Vec<String>,
+    /// Do not execute the matching tasks
+    pub skip: Vec<String>,
+    /// Start from the first matching task
+    pub from: Option<String>,
+    /// Stop after the last matching task
+    pub until: Option<String>,
+}
+
+impl TaskSelection {
+    /// Returns the selected tasks out of the given tasks (in the order of definition).
+    /// Fails if any of the patterns does not match a task.
+    pub fn apply(&self, tasks: &[CommandId]) -> Result<Vec<CommandId>> {
+        let matches = |pattern: &String| -> Result<Vec<usize>> {
+            let positions: Vec<usize> = tasks.iter().enumerate()
+                .filter(|(_, command_id)| glob_match(pattern, command_id))
+                .map(|(position, _)| position)
+                .collect();
+            if positions.is_empty() {
+                Err(anyhow!("No tasks match \"{}\"", pattern))
+            } else {
+                Ok(positions)
+            }
+        };
+
+        let from = self.from.as_ref().map(|pattern| matches(pattern).map(|m| m[0])).transpose()?.unwrap_or(0);
+        let until = self.until.as_ref().map(|pattern| matches(pattern).map(|m| m[m.len() - 1])).transpose()?
+            .unwrap_or_else(|| tasks.len().saturating_sub(1));
+        let only = self.only.iter().map(matches).collect::<Result<Vec<_>>>()?.concat();
+        let skip = self.skip.iter().map(matches).collect::<Result<Vec<_>>>()?.concat();
+
+        Ok(tasks.iter().enumerate()
+            .filter(|(position, _)| *position >= from && *position <= until)
+            .filter(|(position, _)| self.only.is_empty() || only.contains(position))
+            .filter(|(position, _)| !skip.contains(position))
+            .map(|(_, command_id)| command_id.clone())
+            .collect())
     }
 }
 
@@ -109,6 +345,62 @@ pub struct FlowBuilder {
     pub routines: Routines,
     /// Global execution policy
     pub policy: ExecutionPolicy,
+    /// Paths of the command definitions within the job file (used for error locations)
+    pub paths: HashMap<CommandId, String>,
+    /// Directory the job working directory is resolved from (the current directory by default).
+    /// The included files are resolved from the directory of the job file instead (if it is known).
+    pub cwd: Option<PathBuf>,
+    /// Template tasks which the tasks can extend
+    pub templates: config::Templates,
+    /// Origins of the tasks merged from the included files
+    pub origins: Origins,
+    /// Errors found while parsing the tasks, which are reported together
+    pub errors: Vec<anyhow::Error>,
+}
+
+/// Definition of a task within the merged task lists of a job
+#[derive(Debug, Clone)]
+struct Origin {
+    /// Path of the task within the merged job (e.g. `tasks.3`)
+    merged: String,
+    /// Path the errors of the task are reported at within the job file (e.g. `tasks.1` or `include.0`)
+    path: String,
+    /// Included file the task is defined in
+    file: Option<String>,
+}
+
+/// Origins of the tasks of a job with included files, used to report the errors where the tasks are defined
+#[derive(Debug, Clone, Default)]
+pub struct Origins(Vec<Origin>);
+
+impl Origins {
+    /// Finds the origin of the task containing the given path, together with the rest of the path
+    fn find<'a>(&self, path: &'a str) -> Option<(&Origin, &'a str)> {
+        self.0.iter().find_map(|origin| path.strip_prefix(&origin.merged)
+            .filter(|rest| rest.is_empty() || rest.starts_with('.'))
+            .map(|rest| (origin, rest)))
+    }
+
+    /// Returns the included file the task at the given path is defined in
+    pub fn file(&self, path: &str) -> Option<&str> {
+        self.find(path).and_then(|(origin, _)| origin.file.as_deref())
+    }
+
+    /// Moves the location of the error to the definition of the task within the job file.
+    /// The errors of the included tasks are reported at the include and name the included file.
+    pub fn relocate(&self, error: anyhow::Error) -> anyhow::Error {
+        let located = match error.downcast_ref::<LocatedError>() {
+            Some(located) => located,
+            None => return error,
+        };
+        match self.find(&located.path) {
+            Some((Origin { path, file: Some(file), .. }, _)) => LocatedError::at(
+                path, format!("{} (included from {})", located.message, file)
+            ),
+            Some((Origin { path, file: None, .. }, rest)) => LocatedError::at(&format!("{}{}", path, rest), &located.message),
+            None => error,
+        }
+    }
 }
 
 impl FlowBuilder {
@@ -117,33 +409,161 @@ impl FlowBuilder {
             dependencies: HashMap::new(),
             routines: HashMap::new(),
             policy: ExecutionPolicy::default(),
+            paths: HashMap::new(),
+            cwd: None,
+            templates: config::Templates::new(),
+            origins: Origins::default(),
+            errors: Vec::new(),
         }
     }
 
-    /// Parses a routine from a list of tasks
+    /// Merges the included files into the job. The included tasks, hooks and log handlers come before
+    /// the ones of the job, while the env and the templates of the job take precedence over the included ones.
+    fn resolve_includes(&mut self, job: &config::Job) -> Result<config::Job> {
+        let base = match (job.path.as_ref().and_then(|path| path.parent()), &self.cwd) {
+            (Some(job_dir), _) => job_dir.to_path_buf(),
+            (None, Some(cwd)) => cwd.clone(),
+            (None, None) => std::env::current_dir()?,
+        };
+        let mut tasks = config::Tasks::new();
+        let mut hooks = config::Hooks::new();
+        let mut env = Env::default();
+        let mut logging = config::LogHandlers::new();
+        for (index, file) in job.include.iter().enumerate() {
+            let path = format!("include.{}", index);
+            let include = config::Include::read(&resolve_cwd(&base, Some(file)))
+                .map_err(|e| LocatedError::at(&path, e))?;
+            self.merge_tasks(&mut tasks, include.tasks, "tasks", |_| path.clone(), Some(file));
+            for (hook, hook_tasks) in include.hooks.unwrap_or_default() {
+                let hook_path = format!("hooks.{}", hook);
+                self.merge_tasks(hooks.entry(hook).or_default(), hook_tasks, &hook_path, |_| path.clone(), Some(file));
+            }
+            env.extend(include.env.unwrap_or_default());
+            logging.extend(include.logging.unwrap_or_default());
+            self.templates.extend(include.templates.unwrap_or_default());
+        }
+
+        self.merge_tasks(&mut tasks, job.tasks.clone(), "tasks", |index| format!("tasks.{}", index), None);
+        for (hook, hook_tasks) in job.hooks.clone().unwrap_or_default() {
+            let hook_path = format!("hooks.{}", hook);
+            self.merge_tasks(hooks.entry(hook).or_default(), hook_tasks, &hook_path, |index| format!("{}.{}", hook_path, index), None);
+        }
+        env.extend(job.env.clone().unwrap_or_default());
+        logging.extend(job.logging.clone().unwrap_or_default());
+        self.templates.extend(job.templates.clone().unwrap_or_default());
+
+        Ok(config::Job {
+            id: job.id.clone(),
+            name: job.name.clone(),
+            env: Some(env),
+            cwd: job.cwd.clone(),
+            tasks,
+            hooks: Some(hooks),
+            logging: Some(logging),
+            policy: job.policy,
+            timeout: job.timeout,
+            options: job.options.clone(),
+            lock: job.lock.clone(),
+            secrets: job.secrets.clone(),
+            include: Vec::new(),
+            templates: None,
+            path: job.path.clone(),
+        })
+    }
+
+    /// Appends the tasks to a merged task list (at the given path) and records where each of them is defined
+    fn merge_tasks(
+        &mut self,
+        merged: &mut config::Tasks,
+        tasks: config::Tasks,
+        path: &str,
+        origin: impl Fn(usize) -> String,
+        file: Option<&String>,
+    ) {
+        for (index, task) in tasks.into_iter().enumerate() {
+            self.origins.0.push(Origin { merged: format!("{}.{}", path, merged.len()), path: origin(index), file: file.cloned() });
+            merged.push(task);
+        }
+    }
+
+    /// Returns the task with the fields inherited from the template it extends (and the templates it extends)
+    fn extend_task(&self, task: &config::Task, path: &str) -> Result<config::Task> {
+        let mut result = task.clone();
+        let mut extended: Vec<String> = Vec::new();
+        while let Some(name) = result.extends.take() {
+            let location = format!("{}.extends", path);
+            if extended.contains(&name) {
+                return Err(LocatedError::at(&location, format!(
+                    "Task \"{}\" extends the template \"{}\" recursively", task.get_name(), name
+                )));
+            }
+            let template = self.templates.get(&name).ok_or_else(|| LocatedError::at(&location, format!(
+                "Task \"{}\" extends an unknown template \"{}\"", task.get_name(), name
+            )))?;
+            result = result.inherit(template);
+            extended.push(name);
+        }
+        Ok(result)
+    }
+
+    /// Returns the path of a command field within the job file
+    fn field_path(&self, command_id: &CommandId, field: &str) -> String {
+        format!("{}.{}", self.paths[command_id], field)
+    }
+
+    /// Parses a routine from a list of tasks.
+    /// The tasks which can not be parsed are left out and their errors are collected.
     pub fn parse_routine(
         &mut self,
         tasks: &config::Tasks,
         prefix: &str,
         is_hook: bool,
-    ) -> Result<Routine> {
-        let mut commands = Vec::new();
+        path: &str,
+    ) -> Routine {
+        let mut commands: Vec<CommandId> = Vec::new();
 
         for (counter, task) in tasks.iter().enumerate() {
+            // Inherit the template fields first, so that the id may be generated from an inherited name
+            let task_path = format!("{}.{}", path, counter);
+            let task = &match self.extend_task(task, &task_path) {
+                Ok(task) => task,
+                Err(e) => {
+                    self.errors.push(e);
+                    continue;
+                }
+            };
             let task_name = task.get_name();
             let task_id = task.id.clone()
                 .unwrap_or_else(|| generate_id(&task_name, counter, prefix));
 
-            // TODO: handle this in a more verbose way
-            let command = self.parse_command(task, &task_id, is_hook, Some(counter as i32))?;
+            let mut command = match self.parse_command(task, &task_id, is_hook, Some(counter as i32), &task_path) {
+                Ok(command) => command,
+                Err(e) => {
+                    self.errors.push(e);
+                    continue;
+                }
+            };
+            // Main commands without explicit dependencies depend on the previous command
+            if !is_hook && task.needs.is_none() {
+                command.needs = commands.last().cloned().into_iter().collect();
+            }
             if let Some(command) = self.dependencies.insert(task_id.clone(), command) {
-                return Err(anyhow!("Task \"{}\" has a duplicate id. Task \"{}\" has the same id", task_name, command.name));
+                let field = if task.id.is_some() { "id" } else { "name" };
+                let origin = self.paths.get(&task_id)
+                    .and_then(|path| self.origins.file(path))
+                    .map(|file| format!(" (included from {})", file))
+                    .unwrap_or_default();
+                self.errors.push(LocatedError::at(&format!("{}.{}", task_path, field), format!(
+                    "Task \"{}\" has a duplicate id. Task \"{}\"{} has the same id", task_name, command.name, origin
+                )));
+                continue;
             }
+            self.paths.insert(task_id.clone(), task_path);
 
             commands.push(task_id);
         }
 
-        Ok(Routine { commands,  is_hook })
+        Routine { commands,  is_hook }
     }
 
     /// Parses a list of hooks
@@ -152,16 +572,17 @@ impl FlowBuilder {
         hooks: &config::Hooks,
         prefix: &str,
         is_hook: bool,
+        path: &str,
     ) -> Result<Hooks> {
         if is_hook {
-            return Err(anyhow!("Hooks cannot be nested!"));
+            return Err(LocatedError::at(path, "Hooks cannot be nested!"));
         }
 
         let mut result = Hooks::new();
 
         for (hook, tasks) in hooks {
             let routine_id = format!("{}{}", prefix, &hook);
-            let routine = self.parse_routine(tasks, &routine_id, true)?;
+            let routine = self.parse_routine(tasks, &routine_id, true, &format!("{}.{}", path, hook));
             self.routines.insert(routine_id.clone(), routine);
             result.insert(*hook, routine_id);
         }
@@ -176,30 +597,258 @@ impl FlowBuilder {
         prefix: &str,
         is_hook: bool,
         counter: Option<i32>,
+        path: &str,
     ) -> Result<Command> {
         let hooks = if let Some(hooks) = &task.hooks {
-            self.parse_hooks(hooks, prefix, is_hook)?
+            self.parse_hooks(hooks, prefix, is_hook, &format!("{}.hooks", path))?
         } else {
             HashMap::new()
         };
 
-        Ok(Command {
+        if is_hook && task.needs.is_some() {
+            return Err(LocatedError::at(
+                &format!("{}.needs", path), format!("Hook \"{}\" cannot have dependencies!", task.get_name())
+            ));
+        }
+
+        let handler = task.handler.clone().ok_or_else(|| LocatedError::at(path, format!(
+            "Task \"{}\" has no handler. Set one of run, script, command, http or job (or extend a template)",
+            task.get_name()
+        )))?;
+        let mut command = Command {
             task_no: counter,
             name: task.get_name(),
-            handler: task.handler.clone(),
+            handler,
             env: task.env.clone().unwrap_or_default(),
             cwd: task.cwd.clone(),
             is_hook,
             hooks,
             policy: task.policy.unwrap_or(self.policy),
-        })
+            timeout: task.timeout,
+            retry: task.retry.clone(),
+            needs: task.needs.clone().unwrap_or_default(),
+            condition: None,
+        };
+
+        // The errors of the condition and the templates are collected, so that they are all reported
+        if let Some(condition) = &task.condition {
+            match Condition::from_str(condition) {
+                Ok(condition) => command.condition = Some(condition),
+                Err(e) => self.errors.push(LocatedError::at(
+                    &format!("{}.if", path), format!("Task \"{}\" has an invalid condition: {}", command.name, e)
+                )),
+            }
+        }
+
+        // Check the template syntax and variables early, the templates are rendered right before execution
+        for (field, value) in command.templated_fields() {
+            if Template::is_template(value) {
+                if let Err(e) = Template::from_str(value).and_then(|template| template.check_variables(true)) {
+                    self.errors.push(LocatedError::at(&format!("{}.{}", path, field), format!(
+                        "Task \"{}\" has an invalid template in field \"{}\": {}", command.name, field, e
+                    )));
+                }
+            }
+        }
+
+        Ok(command)
     }
 
-    /// Parses a flow from a job configuration
+    /// Validates that the main routine commands form a directed acyclic graph
+    pub fn validate_dependencies(&self, routine: &Routine, errors: &mut Vec<anyhow::Error>) {
+        let mut unknown = false;
+        for command_id in &routine.commands {
+            let command = &self.dependencies[command_id];
+            for need in &command.needs {
+                if !routine.commands.contains(need) {
+                    errors.push(LocatedError::at(
+                        &self.field_path(command_id, "needs"),
+                        format!("Task \"{}\" needs an unknown task \"{}\"", command.name, need),
+                    ));
+                    unknown = true;
+                }
+            }
+        }
+        // The cycles can only be looked for once all the dependencies are known
+        if unknown {
+            return;
+        }
+
+        // Depth first search keeping track of the current path to detect cycles
+        fn visit<'b>(
+            builder: &'b FlowBuilder,
+            command_id: &'b CommandId,
+            path: &mut Vec<&'b CommandId>,
+            visited: &mut HashSet<&'b CommandId>,
+        ) -> Result<()> {
+            if let Some(start) = path.iter().position(|id| *id == command_id) {
+                let cycle: Vec<&str> = path[start..].iter().chain([&command_id])
+                    .map(|id| id.as_str()).collect();
+                return Err(LocatedError::at(
+                    &builder.field_path(command_id, "needs"),
+                    format!("Task dependencies contain a cycle: {}", cycle.join(" -> ")),
+                ));
+            }
+            if !visited.insert(command_id) {
+                return Ok(());
+            }
+
+            path.push(command_id);
+            for need in &builder.dependencies[command_id].needs {
+                visit(builder, need, path, visited)?;
+            }
+            path.pop();
+            Ok(())
+        }
+
+        let mut visited = HashSet::new();
+        if let Err(e) = routine.commands.iter().try_for_each(|command_id| visit(self, command_id, &mut Vec::new(), &mut visited)) {
+            errors.push(e);
+        }
+    }
+
+    /// Validates that the conditions only reference existing main routine tasks.
+    /// Conditions of the main routine tasks may only reference the tasks they (transitively) depend on,
+    /// since the other tasks are not guaranteed to have run before them.
+    pub fn validate_conditions(&self, routine: &Routine, errors: &mut Vec<anyhow::Error>) {
+        for (command_id, command) in &self.dependencies {
+            let condition = match &command.condition {
+                Some(condition) => condition,
+                None => continue,
+            };
+            let upstream = routine.commands.contains(command_id).then(|| upstream(&self.dependencies, command_id));
+            for task_id in condition.expression.task_references() {
+                if !routine.commands.contains(task_id) {
+                    errors.push(LocatedError::at(&self.field_path(command_id, "if"), format!(
+                        "Task \"{}\" has a condition referencing an unknown task \"{}\"", command.name, task_id
+                    )));
+                } else if upstream.as_ref().is_some_and(|upstream| !upstream.contains(task_id)) {
+                    errors.push(LocatedError::at(&self.field_path(command_id, "if"), format!(
+                        "Task \"{}\" has a condition referencing task \"{}\", which it does not depend on",
+                        command.name, task_id
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Validates that the script and sub job files exist and that the scripts can be executed
+    /// (unless they are run with a shell).
+    /// The files are resolved from the directory of the job file (if it is known).
+    /// Files with templates in their path or working directory are only checked on execution.
+    pub fn validate_files(&self, job: &config::Job, errors: &mut Vec<anyhow::Error>) {
+        let current_dir = match &self.cwd {
+            Some(cwd) => cwd.clone(),
+            None => match std::env::current_dir() {
+                Ok(cwd) => cwd,
+                Err(e) => {
+                    errors.push(e.into());
+                    return;
+                }
+            },
+        };
+        let job_dir = job.path.as_ref().and_then(|path| path.parent());
+        let job_cwd = resolve_cwd(&current_dir, job.cwd.as_ref());
+        let job_cwd_template = job.cwd.iter().any(|cwd| Template::is_template(cwd));
+        for (command_id, command) in &self.dependencies {
+            let (field, file, kind) = match &command.handler {
+                TaskHandler::Script(script) => ("script", &script.script, "script"),
+                TaskHandler::Job(job) => ("job", &job.job, "job file"),
+                _ => continue,
+            };
+            if Template::is_template(file) {
+                continue;
+            }
+            let path: PathBuf = match job_dir {
+                Some(job_dir) => resolve_cwd(job_dir, Some(file)),
+                _ if job_cwd_template || command.cwd.iter().any(|cwd| Template::is_template(cwd)) => continue,
+                _ => resolve_cwd(&resolve_cwd(&job_cwd, command.cwd.as_ref()), Some(file)),
+            }.components().collect();
+            if !path.is_file() {
+                errors.push(LocatedError::at(&self.field_path(command_id, field), format!(
+                    "Task \"{}\" has a {} which does not exist: {:?}", command.name, kind, path
+                )));
+                continue;
+            }
+            match &command.handler {
+                TaskHandler::Script(script) if !script.has_shell() && !is_executable(&path) => {
+                    errors.push(LocatedError::at(&self.field_path(command_id, field), format!(
+                        "Task \"{}\" has a script which is not executable: {:?}. Make it executable or set its shell",
+                        command.name, path
+                    )));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Validates that the timestamp formats of the log handlers are valid strftime formats
+    pub fn validate_logging(&self, job: &config::Job, errors: &mut Vec<anyhow::Error>) {
+        for (index, handler) in job.logging.iter().flatten().enumerate() {
+            let Some(format) = &handler.options.timestamp_format else {
+                continue;
+            };
+            if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                errors.push(LocatedError::at(&format!("logging.{}.timestamp_format", index), format!(
+                    "Log handler \"{}\" has an invalid timestamp format: \"{}\"",
+                    handler.name.as_deref().unwrap_or(handler.handler.name()), format
+                )));
+            }
+        }
+    }
+
+    /// Validates that the templates only reference existing main routine tasks
+    pub fn validate_templates(&self, routine: &Routine, check_upstream: bool, errors: &mut Vec<anyhow::Error>) {
+        for (command_id, command) in &self.dependencies {
+            // Like the conditions, the templates of the main routine tasks may only reference upstream tasks
+            let upstream = (check_upstream && routine.commands.contains(command_id))
+                .then(|| upstream(&self.dependencies, command_id));
+            for (field, value) in command.templated_fields() {
+                let template = match Template::from_str(value) {
+                    Ok(template) => template,
+                    Err(_) => continue,
+                };
+                for task_id in template.task_references() {
+                    if !routine.commands.iter().any(|id| id == task_id) {
+                        errors.push(LocatedError::at(&self.field_path(command_id, &field), format!(
+                            "Task \"{}\" has a template in field \"{}\" referencing an unknown task \"{}\"",
+                            command.name, field, task_id
+                        )));
+                    } else if upstream.as_ref().is_some_and(|upstream| !upstream.contains(task_id)) {
+                        errors.push(LocatedError::at(&self.field_path(command_id, &field), format!(
+                            "Task \"{}\" has a template in field \"{}\" referencing task \"{}\", which it does not depend on",
+                            command.name, field, task_id
+                        )));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parses a flow from a job configuration, failing with the first error found
     pub fn parse_flow(
-        mut self,
+        self,
         job: &config::Job,
     ) -> Result<Flow> {
+        self.parse_flow_all(job).map_err(|mut errors| errors.remove(0))
+    }
+
+    /// Parses a flow from a job configuration, failing with all the errors found
+    pub fn parse_flow_all(
+        mut self,
+        job: &config::Job,
+    ) -> std::result::Result<Flow, Vec<anyhow::Error>> {
+        // Merge the included files first, so that the ids are generated and checked for the merged tasks
+        let job = self.resolve_includes(job).map_err(|e| vec![e])?;
+        let origins = self.origins.clone();
+        self.parse_job(&job).map_err(|errors| errors.into_iter().map(|e| origins.relocate(e)).collect())
+    }
+
+    /// Parses the job, the tasks are validated together once they have all been parsed
+    fn parse_job(
+        mut self,
+        job: &config::Job,
+    ) -> std::result::Result<Flow, Vec<anyhow::Error>> {
         // Set the job identifier if not yet set (usually the filename unless it is overridden)
         let id = job.id.clone().unwrap_or_else(|| format_identifier(&job.name));
 
@@ -208,13 +857,43 @@ impl FlowBuilder {
 
         // Parse the global hooks
         let hooks = if let Some(hooks) = &job.hooks {
-            self.parse_hooks(hooks, "", false)?
+            self.parse_hooks(hooks, "", false, "hooks").map_err(|e| vec![e])?
         } else {
             HashMap::new()
         };
 
-        // Parse the main routine and add it to the list of routines
-        let main_routine = self.parse_routine(&job.tasks, "", false)?;
+        // Parse the main routine
+        let main_routine = self.parse_routine(&job.tasks, "", false, "tasks");
+
+        let env = job.env.clone().unwrap_or_default();
+        let job_fields = job.cwd.iter().map(|cwd| ("cwd".to_string(), cwd))
+            .chain(env.iter().map(|(key, value)| (format!("env.{}", key), value)));
+        for (field, value) in job_fields {
+            if Template::is_template(value) {
+                if let Err(e) = Template::from_str(value).and_then(|template| template.check_variables(false)) {
+                    self.errors.push(LocatedError::at(&field, format!(
+                        "Job \"{}\" has an invalid template in field \"{}\": {}", job.name, field, e
+                    )));
+                }
+            }
+        }
+
+        // The tasks are only validated against each other once they have all been parsed
+        let mut errors = std::mem::take(&mut self.errors);
+        if errors.is_empty() {
+            self.validate_dependencies(&main_routine, &mut errors);
+            // The upstream tasks can only be resolved within a valid dependency graph
+            let valid_dependencies = errors.is_empty();
+            if valid_dependencies {
+                self.validate_conditions(&main_routine, &mut errors);
+            }
+            self.validate_templates(&main_routine, valid_dependencies, &mut errors);
+            self.validate_files(job, &mut errors);
+            self.validate_logging(job, &mut errors);
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
         self.routines.insert(MAIN_ROUTINE_NAME.to_string(), main_routine);
 
         Ok(Flow {
@@ -222,9 +901,14 @@ impl FlowBuilder {
             name: job.name.clone(),
             dependencies: self.dependencies,
             routines: self.routines,
-            env: job.env.clone().unwrap_or_default(),
+            env,
             cwd: job.cwd.clone(),
+            lock: job.lock.clone(),
+            timeout: job.timeout,
+            secrets: job.secrets.clone(),
+            logging: job.logging.clone().unwrap_or_default(),
             hooks,
+            deselected: HashSet::new(),
         })
     }
 }
@@ -232,17 +916,14 @@ impl FlowBuilder {
 /// Stack item encoding information about current execution state of a routine
 #[derive(Debug, Clone)]
 pub struct StackItem {
-    /// Current routine
-    pub routine: RoutineId,
+    /// Commands of the current routine
+    pub commands: Vec<CommandId>,
     /// Current position pointing to a command in routine
-    /// value of -1 means that routine has yet to schedule before hooks
     pub position: i32,
     /// Whether hooks for the current command have been scheduled
     pub scheduled: bool,
     /// Whether the current command is a hook
     pub is_hook: bool,
-    /// Length of the current routine
-    pub length: i32,
     /// Reference to main current (non hook) routine command
     /// If none, then the main routine is finished
     pub focus_command: Option<CommandId>,
@@ -255,12 +936,12 @@ pub struct FlowIterator<'a> {
 }
 
 impl <'a> FlowIterator<'a> {
-    pub fn new(flow: &'a Flow) -> Self {
+    pub fn new(flow: &'a Flow, commands: Vec<CommandId>, is_hook: bool) -> Self {
         let mut res = FlowIterator {
             flow,
             routine_stack: Vec::new(),
         };
-        res.push(MAIN_ROUTINE_NAME.to_string(), None);
+        res.push_commands(commands, is_hook, None);
         res
     }
 
@@ -300,9 +981,8 @@ impl <'a> FlowIterator<'a> {
     }
 
     /// Returns info about current command given a stack item
-    fn get_command(&self, head: &StackItem) -> Option<(&CommandId, &Command)> {
-        let routine = self.routine(&head.routine);
-        let command_id = routine.commands.get(head.position as usize).expect("Command not found");
+    fn get_command<'b>(&self, head: &'b StackItem) -> Option<(&'b CommandId, &Command)> {
+        let command_id = head.commands.get(head.position as usize).expect("Command not found");
         let command = self.command(command_id);
         Some((command_id, command))
     }
@@ -310,12 +990,16 @@ impl <'a> FlowIterator<'a> {
     /// Pushes a new routine onto the stack
     fn push(&mut self, routine_id: RoutineId, focus: Option<CommandId>) {
         let routine = self.routine(&routine_id);
+        self.push_commands(routine.commands.clone(), routine.is_hook, focus);
+    }
+
+    /// Pushes a list of commands onto the stack
+    fn push_commands(&mut self, commands: Vec<CommandId>, is_hook: bool, focus: Option<CommandId>) {
         let item = StackItem{
-            routine: routine_id,
-            position: -1,
+            commands,
+            position: 0,
             scheduled: false,
-            is_hook: routine.is_hook,
-            length: routine.commands.len() as i32,
+            is_hook,
             focus_command: focus,
         };
 
@@ -336,7 +1020,7 @@ impl <'a> FlowIterator<'a> {
         let hook_type = if result.is_success() { Hook::OnSuccess } else { Hook::OnFailure };
 
         let command = self.command(command_id);
-        if !command.is_hook {
+        if !command.is_hook && !result.is_skipped() {
             // Add a task-specific hook
             if let Some(hook) = command.hooks.get(&hook_type).cloned() {
                 self.push(hook, Some(command_id.clone()));
@@ -358,27 +1042,10 @@ impl <'a> Iterator for FlowIterator<'a> {
             // Empty Stack, we are done
             None => None,
             // We are at the end the current routine
-            Some(item) if item.position == item.length => {
+            Some(item) if item.position as usize == item.commands.len() => {
                 self.pop();
-                if !item.is_hook {
-                    // We are at the end of the main routine, schedule the after job hook
-                    if let Some(hook_id) = self.flow.hooks.get(&Hook::AfterJob) {
-                        self.push(hook_id.clone(), None);
-                    }
-                }
                 self.next()
             },
-            // We are at the start of the current routine
-            Some(item) if item.position == -1 => {
-                self.increment_position();
-                if !item.is_hook {
-                    // We are at the start of the main routine, schedule the before job hook
-                    if let Some(hook_id) = self.flow.hooks.get(&Hook::BeforeJob) {
-                        self.push(hook_id.clone(), None);
-                    }
-                }
-                self.next()
-            }
             // We at a main routine command (non hook) which has not been scheduled
             Some(item) if !item.is_hook && !item.scheduled => {
                 self.set_scheduled();
@@ -416,4 +1083,356 @@ impl <'a> Iterator for FlowIterator<'a> {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+    use crate::common::{HumanDuration, LocatedError};
+    use crate::config;
+    use crate::flow::{Flow, FlowBuilder, TaskSelection};
+
+    fn parse_job(yaml: &str) -> anyhow::Result<Flow> {
+        let job: config::Job = serde_yaml::from_str(yaml).expect("Failed to parse job");
+        Flow::parse(&job)
+    }
+
+    #[test]
+    fn test_implicit_and_explicit_needs() {
+        let flow = parse_job(r#"
+name: test
+tasks:
+  - id: a
+    run: "true"
+  - id: b
+    run: "true"
+  - id: c
+    run: "true"
+    needs: []
+  - id: d
+    run: "true"
+    needs: [a, c]
+"#).unwrap();
+
+        assert!(flow.command(&"a".to_string()).unwrap().needs.is_empty());
+        assert_eq!(flow.command(&"b".to_string()).unwrap().needs, vec!["a".to_string()]);
+        assert!(flow.command(&"c".to_string()).unwrap().needs.is_empty());
+        assert_eq!(flow.upstream(&"d".to_string()).len(), 2);
+    }
+
+    #[test]
+    fn test_unknown_needs() {
+        let err = parse_job(r#"
+name: test
+tasks:
+  - id: a
+    run: "true"
+    needs: [b]
+"#).unwrap_err();
+        assert!(err.to_string().contains("unknown task \"b\""));
+    }
+
+    #[test]
+    fn test_cyclic_needs() {
+        let err = parse_job(r#"
+name: test
+tasks:
+  - id: a
+    run: "true"
+    needs: [c]
+  - id: b
+    run: "true"
+  - id: c
+    run: "true"
+"#).unwrap_err();
+        assert!(err.to_string().contains("a -> c -> b -> a"), "{}", err);
+    }
+
+    #[test]
+    fn test_invalid_template() {
+        let err = parse_job(r#"
+name: test
+tasks:
+  - id: a
+    cwd: ${{ DIR | unknown }}
+    run: "true"
+"#).unwrap_err();
+        assert!(err.to_string().contains("invalid template in field \"cwd\""), "{}", err);
+    }
+
+    #[test]
+    fn test_template_unknown_task() {
+        let err = parse_job(r#"
+name: test
+tasks:
+  - id: a
+    name: Print file
+    run: echo ${{ tasks.b.outputs.FILE }}
+"#).unwrap_err();
+        assert!(err.to_string().contains("field \"run\" referencing an unknown task \"b\""), "{}", err);
+    }
+
+    #[test]
+    fn test_template_not_upstream() {
+        let err = parse_job(r#"
+name: test
+tasks:
+  - id: a
+    run: echo "FILE=a.txt" >> $NAUMAN_OUTPUT_FILE
+  - id: b
+    run: echo "FILE=b.txt" >> $NAUMAN_OUTPUT_FILE
+    needs: []
+  - id: c
+    name: Concatenate
+    run: cat ${{ tasks.a.outputs.FILE }} ${{ tasks.b.outputs.FILE }}
+    needs: [a]
+"#).unwrap_err();
+        assert!(err.to_string().contains("field \"run\" referencing task \"b\", which it does not depend on"), "{}", err);
+        assert_eq!(err.downcast_ref::<LocatedError>().unwrap().path, "tasks.2.run");
+    }
+
+    #[test]
+    fn test_condition_not_upstream() {
+        let err = parse_job(r#"
+name: test
+tasks:
+  - id: a
+    run: "true"
+  - id: b
+    run: "true"
+    needs: []
+  - id: c
+    if: tasks.a.status == success && tasks.b.status == success
+    run: "true"
+    needs: [a]
+"#).unwrap_err();
+        assert!(err.to_string().contains("referencing task \"b\", which it does not depend on"), "{}", err);
+        assert_eq!(err.downcast_ref::<LocatedError>().unwrap().path, "tasks.2.if");
+
+        let err = parse_job(r#"
+name: test
+tasks:
+  - id: a
+    if: tasks.a.status == success
+    run: "true"
+"#).unwrap_err();
+        assert!(err.to_string().contains("referencing task \"a\""), "{}", err);
+    }
+
+    #[test]
+    fn test_timestamp_format_validation() {
+        let err = parse_job(r#"
+name: test
+tasks:
+  - run: "true"
+logging:
+  - type: console
+    timestamps: wall
+    timestamp_format: "%H:%M:%S"
+  - type: console
+    name: broken
+    timestamps: wall
+    timestamp_format: "%Q"
+"#).unwrap_err();
+        assert_eq!(err.to_string(), "Log handler \"broken\" has an invalid timestamp format: \"%Q\"");
+        assert_eq!(err.downcast_ref::<LocatedError>().unwrap().path, "logging.1.timestamp_format");
+    }
+
+    #[test]
+    fn test_file_validation() {
+        let dir = std::env::temp_dir().join(format!("nauman-flow-scripts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("plain.sh"), "echo").unwrap();
+        let job = |task: &str| parse_job(&format!("name: test\ncwd: {}\ntasks:\n  - {}\n", dir.display(), task));
+
+        let missing = job("script: ./missing.sh").unwrap_err();
+        let not_executable = job("script: ./plain.sh").unwrap_err();
+        let with_shell = job("{ script: ./plain.sh, shell: sh }");
+        let templated = job("script: ./${{ env.SCRIPT }}");
+        let missing_job = job("job: ./missing.yml").unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(missing.to_string().contains("script which does not exist"), "{}", missing);
+        assert!(not_executable.to_string().contains("script which is not executable"), "{}", not_executable);
+        assert!(with_shell.is_ok());
+        assert!(templated.is_ok());
+        assert!(missing_job.to_string().contains("job file which does not exist"), "{}", missing_job);
+    }
+
+    #[test]
+    fn test_includes() {
+        let dir = std::env::temp_dir().join(format!("nauman-flow-includes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("common.yml"), r#"
+env:
+  TARGET: staging
+  REGION: eu
+tasks:
+  - name: Lint
+    run: "true"
+hooks:
+  on_failure:
+    - name: Notify
+      run: "true"
+templates:
+  quiet:
+    run: "true"
+"#).unwrap();
+        std::fs::write(dir.join("duplicate.yml"), "tasks:\n  - id: build\n    name: Build\n    run: \"true\"\n").unwrap();
+        let job = |tasks: &str| {
+            let job: config::Job = serde_yaml::from_str(&format!(
+                "name: test\ninclude: [common.yml, duplicate.yml]\nenv:\n  TARGET: production\ntasks:\n{}", tasks
+            )).unwrap();
+            FlowBuilder { cwd: Some(dir.clone()), ..FlowBuilder::new() }.parse_flow(&job)
+        };
+
+        let flow = job("  - name: Test\n    extends: quiet\n");
+        let duplicate = job("  - id: build\n    name: Compile\n    run: \"true\"\n").unwrap_err();
+        let unknown = job("  - name: Test\n    extends: loud\n").unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let flow = flow.unwrap();
+        assert_eq!(flow.tasks(), &vec!["000_lint".to_string(), "build".to_string(), "002_test".to_string()]);
+        assert!(flow.hooks.contains_key(&config::Hook::OnFailure));
+        assert_eq!(flow.env.get("TARGET"), Some(&"production".to_string()));
+        assert_eq!(flow.env.get("REGION"), Some(&"eu".to_string()));
+        assert!(duplicate.to_string().contains("Task \"Build\" (included from duplicate.yml) has the same id"), "{}", duplicate);
+        assert_eq!(duplicate.downcast_ref::<LocatedError>().unwrap().path, "tasks.0.id");
+        assert_eq!(unknown.downcast_ref::<LocatedError>().unwrap().path, "tasks.0.extends");
+    }
+
+    #[test]
+    fn test_includes_relative_to_job_file() {
+        // The job file is outside of the current directory, which the includes are not resolved from
+        let dir = std::env::temp_dir().join(format!("nauman-flow-include-dir-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        std::fs::write(dir.join("shared").join("common.yml"), "tasks:\n  - name: Lint\n    run: \"true\"\n").unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, "name: test\ninclude: [./shared/common.yml]\ntasks:\n  - run: \"true\"\n").unwrap();
+
+        let (job, _) = config::Job::read(job_path.to_str().unwrap()).unwrap();
+        let flow = Flow::parse(&job);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_ne!(std::env::current_dir().unwrap(), dir);
+        assert_eq!(flow.unwrap().tasks().len(), 2);
+    }
+
+    #[test]
+    fn test_scripts_relative_to_job_file() {
+        // The scripts are resolved from the job file directory, not from the job cwd
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("nauman-flow-script-dir-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("scripts")).unwrap();
+        std::fs::write(dir.join("scripts").join("run.sh"), "#!/bin/sh\necho").unwrap();
+        std::fs::set_permissions(dir.join("scripts").join("run.sh"), std::fs::Permissions::from_mode(0o755)).unwrap();
+        let job_path = dir.join("job.yml");
+        let job = |script: &str| {
+            std::fs::write(&job_path, format!("name: test\ncwd: /\ntasks:\n  - script: {}\n", script)).unwrap();
+            let (job, _) = config::Job::read(job_path.to_str().unwrap()).unwrap();
+            Flow::parse(&job)
+        };
+
+        let flow = job("./scripts/run.sh");
+        let missing = job("./run.sh").unwrap_err();
+        let expected = format!("{:?}", dir.canonicalize().unwrap().join("run.sh"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(flow.is_ok(), "{}", flow.unwrap_err());
+        assert!(missing.to_string().ends_with(&expected), "{}", missing);
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let dir = std::env::temp_dir().join(format!("nauman-flow-include-errors-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("invalid.yml"), "tasks:\n  - name: Broken\n    run: \"true\"\n    if: \"${{ env.A ==\"\n").unwrap();
+        let job = |include: &str| {
+            let job: config::Job = serde_yaml::from_str(&format!(
+                "name: test\ninclude: [{}]\ntasks:\n  - run: \"true\"\n", include
+            )).unwrap();
+            FlowBuilder { cwd: Some(dir.clone()), ..FlowBuilder::new() }.parse_flow(&job)
+        };
+
+        let missing = job("missing.yml").unwrap_err();
+        let invalid = job("invalid.yml").unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(missing.downcast_ref::<LocatedError>().unwrap().path, "include.0");
+        let invalid = invalid.downcast_ref::<LocatedError>().unwrap();
+        assert_eq!(invalid.path, "include.0");
+        assert!(invalid.message.ends_with("(included from invalid.yml)"), "{}", invalid.message);
+    }
+
+    #[test]
+    fn test_extends() {
+        let flow = parse_job(r#"
+name: test
+templates:
+  base:
+    run: echo base
+    timeout: 30
+    env:
+      LEVEL: base
+      SHARED: base
+  deploy:
+    extends: base
+    name: Deploy
+    env:
+      LEVEL: deploy
+tasks:
+  - extends: deploy
+    env:
+      SHARED: task
+  - name: Other
+    extends: base
+    run: echo other
+"#).unwrap();
+
+        let deploy = flow.command(&"000_deploy".to_string()).unwrap();
+        assert_eq!(deploy.name, "Deploy");
+        assert_eq!(deploy.timeout, Some(HumanDuration::from_secs(30)));
+        assert_eq!(deploy.env.get("LEVEL"), Some(&"deploy".to_string()));
+        assert_eq!(deploy.env.get("SHARED"), Some(&"task".to_string()));
+        let other = flow.command(&"001_other".to_string()).unwrap();
+        assert!(matches!(&other.handler, config::TaskHandler::Shell(shell) if shell.run == "echo other"), "{:?}", other.handler);
+    }
+
+    #[test]
+    fn test_extends_errors() {
+        let cyclic = parse_job(r#"
+name: test
+templates:
+  a:
+    extends: b
+  b:
+    extends: a
+tasks:
+  - name: Task
+    extends: a
+"#).unwrap_err();
+        let no_handler = parse_job("name: test\ntasks:\n  - name: Task\n").unwrap_err();
+        assert!(cyclic.to_string().contains("extends the template \"a\" recursively"), "{}", cyclic);
+        assert!(no_handler.to_string().contains("Task \"Task\" has no handler"), "{}", no_handler);
+    }
+
+    #[test_case(&[], &[], None, None, &["lint", "build", "test_unit", "test_e2e", "deploy"])]
+    #[test_case(&["test_*"], &[], None, None, &["test_unit", "test_e2e"])]
+    #[test_case(&[], &["test_e2e", "deploy"], None, None, &["lint", "build", "test_unit"])]
+    #[test_case(&[], &[], Some("build"), Some("test_*"), &["build", "test_unit", "test_e2e"])]
+    #[test_case(&["lint", "deploy"], &[], Some("build"), None, &["deploy"])]
+    fn test_task_selection(only: &[&str], skip: &[&str], from: Option<&str>, until: Option<&str>, expected: &[&str]) {
+        let tasks: Vec<String> = ["lint", "build", "test_unit", "test_e2e", "deploy"].iter().map(|id| id.to_string()).collect();
+        let selection = TaskSelection {
+            only: only.iter().map(|id| id.to_string()).collect(),
+            skip: skip.iter().map(|id| id.to_string()).collect(),
+            from: from.map(str::to_string),
+            until: until.map(str::to_string),
+        };
+        assert_eq!(selection.apply(&tasks).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_task_selection_no_match() {
+        let tasks = vec!["lint".to_string()];
+        let selection = TaskSelection { only: vec!["build*".to_string()], ..TaskSelection::default() };
+        let err = selection.apply(&tasks).unwrap_err();
+        assert!(err.to_string().contains("No tasks match \"build*\""), "{}", err);
+    }
+}
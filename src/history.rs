@@ -0,0 +1,273 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use prettytable::{Cell, Row, Table, row};
+use serde::{Serialize, Deserialize};
+use crate::execution::{ExecutionResult, JobResult, JobStatus, RunInfo};
+use crate::flow::{CommandId, Flow};
+use crate::pprint::truncate_string;
+use crate::utils::mask_text;
+
+/// Name of the history file (within the log directory)
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// Recorded result of a single command of a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub command_id: CommandId,
+    pub name: String,
+    pub is_hook: bool,
+    /// Task the hook was executed for (if it is a task level hook)
+    pub focus_id: Option<CommandId>,
+    /// Status as used in the condition expressions (success, failure, skipped, ...)
+    pub status: String,
+    pub exit_code: i32,
+    pub attempts: u32,
+    pub duration: Option<f64>,
+    /// Results of the commands of the sub job run by the command
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nested: Vec<TaskRecord>,
+}
+
+impl TaskRecord {
+    fn new(name: String, is_hook: bool, result: &ExecutionResult) -> Self {
+        TaskRecord {
+            command_id: result.command_id.clone(),
+            name,
+            is_hook,
+            focus_id: result.focus_id.clone(),
+            status: result.status().to_string(),
+            exit_code: result.exit_code,
+            attempts: result.attempts,
+            duration: result.duration.map(|duration| duration.as_secs_f64()),
+            nested: result.nested.iter()
+                .map(|nested| TaskRecord::new(
+                    nested.name.clone().unwrap_or_else(|| nested.command_id.clone()), nested.is_hook, nested
+                ))
+                .collect(),
+        }
+    }
+
+    fn mask_secrets(&mut self, secrets: &[String]) {
+        self.name = mask_text(&self.name, secrets);
+        for nested in &mut self.nested {
+            nested.mask_secrets(secrets);
+        }
+    }
+}
+
+/// Recorded result of a job run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub run_id: String,
+    pub job_id: String,
+    pub job_name: String,
+    pub status: JobStatus,
+    pub exit_code: i32,
+    pub started_at: DateTime<Local>,
+    pub finished_at: DateTime<Local>,
+    pub host: Option<String>,
+    /// What has started the run (e.g. manual, automated or resume)
+    pub trigger: String,
+    pub job_path: Option<PathBuf>,
+    /// Log directory of the run (if it was created)
+    pub log_dir: Option<PathBuf>,
+    pub tasks: Vec<TaskRecord>,
+}
+
+impl RunRecord {
+    pub fn new(flow: &Flow, run: &RunInfo, log_dir: Option<PathBuf>, result: &JobResult) -> Self {
+        let tasks = result.results.iter()
+            .map(|(command_id, result)| {
+                let command = flow.command(command_id).expect("Command not found");
+                TaskRecord::new(result.name.clone().unwrap_or_else(|| command.name.clone()), command.is_hook, result)
+            })
+            .collect();
+
+        RunRecord {
+            run_id: run.run_id.clone(),
+            job_id: run.job_id.clone(),
+            job_name: run.job_name.clone(),
+            status: result.status,
+            exit_code: result.exit_code,
+            started_at: run.started_at,
+            finished_at: Local::now(),
+            host: hostname(),
+            trigger: run.trigger.clone(),
+            job_path: run.job_path.clone(),
+            log_dir,
+            tasks,
+        }
+    }
+
+    /// Masks the secret values within the job and task names
+    pub fn mask_secrets(&mut self, secrets: &[String]) {
+        self.job_name = mask_text(&self.job_name, secrets);
+        for task in &mut self.tasks {
+            task.mask_secrets(secrets);
+        }
+    }
+
+    pub fn duration(&self) -> chrono::Duration {
+        self.finished_at - self.started_at
+    }
+
+    /// Returns a table listing the results of the commands of the run
+    pub fn tasks_table(&self) -> Table {
+        let mut table = Table::new();
+        table.add_row(row!["Task", "Name", "Status", "Exit code", "Time (in s)", "Attempts"]);
+        for task in &self.tasks {
+            table.add_row(Row::new(vec![
+                Cell::new(if task.is_hook { "hook" } else { "task" }),
+                Cell::new(&truncate_string(&task.name, 60)),
+                Cell::new(&task.status).style_spec(status_style(&task.status)),
+                Cell::new(&task.exit_code.to_string()),
+                Cell::new(&task.duration.map(|d| format!("{:.1}", d)).unwrap_or_else(|| "-".to_string())),
+                Cell::new(&if task.attempts > 0 { task.attempts.to_string() } else { "-".to_string() }),
+            ]));
+        }
+        table
+    }
+
+    /// Returns the paths of the log files written during the run
+    pub fn log_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        let mut stack: Vec<PathBuf> = self.log_dir.iter().cloned().collect();
+        while let Some(dir) = stack.pop() {
+            for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    stack.push(path);
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+}
+
+/// Returns a table listing the given runs
+pub fn runs_table(records: &[RunRecord]) -> Table {
+    let mut table = Table::new();
+    table.add_row(row!["Run", "Status", "Started", "Time (in s)", "Tasks", "Trigger"]);
+    for record in records {
+        let tasks: Vec<&TaskRecord> = record.tasks.iter().filter(|task| !task.is_hook).collect();
+        let succeeded = tasks.iter().filter(|task| task.status == "success").count();
+        let status = format!("{:?}", record.status).to_lowercase();
+        table.add_row(Row::new(vec![
+            Cell::new(&record.run_id),
+            Cell::new(&status).style_spec(status_style(&status)),
+            Cell::new(&record.started_at.format("%Y-%m-%d %H:%M:%S").to_string()),
+            Cell::new(&record.duration().num_seconds().to_string()),
+            Cell::new(&format!("{}/{}", succeeded, tasks.len())),
+            Cell::new(&record.trigger),
+        ]));
+    }
+    table
+}
+
+fn status_style(status: &str) -> &'static str {
+    match status {
+        "success" => "Fg",
+        "skipped" | "deselected" | "aborted" => "Fb",
+        _ => "Fr",
+    }
+}
+
+/// Returns the name of the current host
+fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    nix::unistd::gethostname(&mut buffer).ok()
+        .map(|name| name.to_string_lossy().to_string())
+}
+
+/// History of the job runs stored as json lines (one run per line)
+pub struct History {
+    pub path: PathBuf,
+}
+
+impl History {
+    /// Returns the history stored in the given log directory
+    pub fn in_dir(log_dir: &Path) -> Self {
+        History { path: log_dir.join(HISTORY_FILE) }
+    }
+
+    /// Appends a run record to the history
+    pub fn append(&self, record: &RunRecord) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)
+            .map_err(|e| anyhow!("Failed to open history file: {:?}. Error: {}", self.path, e))?;
+        writeln!(file, "{}", serde_json::to_string(record)?)
+            .map_err(|e| anyhow!("Failed to write history file: {:?}. Error: {}", self.path, e))
+    }
+
+    /// Loads all the run records in the order they were recorded.
+    /// Lines which can not be parsed (i.e. partially written) are ignored.
+    pub fn load(&self) -> Result<Vec<RunRecord>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let contents = std::fs::read_to_string(&self.path)
+            .map_err(|e| anyhow!("Failed to read history file: {:?}. Error: {}", self.path, e))?;
+        Ok(contents.lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Finds the last record of a run given its id
+    pub fn find(&self, run_id: &str) -> Result<RunRecord> {
+        self.load()?.into_iter()
+            .rev()
+            .find(|record| record.run_id == run_id)
+            .ok_or_else(|| anyhow!("Run \"{}\" not found in history file: {:?}", run_id, self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config;
+    use crate::execution::{ExecutionResult, JobResult, JobStatus, RunInfo, TaskOutcome};
+    use crate::flow::Flow;
+    use crate::history::{History, RunRecord};
+
+    #[test]
+    fn test_append_and_load() {
+        let job: config::Job = serde_yaml::from_str(r#"
+name: test
+tasks:
+  - id: a
+    run: "true"
+  - id: b
+    run: "true"
+"#).unwrap();
+        let flow = Flow::parse(&job).unwrap();
+        let mut run = RunInfo::new("test", "test");
+        let result = JobResult {
+            status: JobStatus::Failed,
+            exit_code: 2,
+            results: vec![
+                ("a".to_string(), ExecutionResult { exit_code: 2, ..ExecutionResult::new("a".to_string(), None) }),
+                ("b".to_string(), TaskOutcome::deselected(&"b".to_string()).result),
+            ],
+        };
+
+        let dir = std::env::temp_dir().join(format!("nauman-history-{}", std::process::id()));
+        let history = History::in_dir(&dir);
+        history.append(&RunRecord::new(&flow, &run, None, &result)).unwrap();
+        run.trigger = "resume".to_string();
+        history.append(&RunRecord::new(&flow, &run, None, &JobResult { status: JobStatus::Success, exit_code: 0, results: vec![] })).unwrap();
+
+        let records = history.load().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].tasks[0].status, "failure");
+        assert_eq!(records[0].tasks[0].exit_code, 2);
+        assert_eq!(records[0].tasks[1].status, "deselected");
+        assert_eq!(records[1].trigger, "resume");
+    }
+}
@@ -0,0 +1,432 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned, pki_types::ServerName};
+
+/// Parsed `http://` or `https://` url
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    /// Whether the connection is secured with TLS (`https://`)
+    pub tls: bool,
+    /// Host name or IP address (without the brackets of an IPv6 address)
+    pub host: String,
+    pub port: u16,
+    /// Path including the query string
+    pub path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Self> {
+        let (tls, rest) = match url.split_once("://") {
+            Some(("http", rest)) => (false, rest),
+            Some(("https", rest)) => (true, rest),
+            Some((scheme, _)) => return Err(anyhow!("Invalid url \"{}\": unsupported scheme \"{}\"", url, scheme)),
+            None => return Err(anyhow!("Invalid url \"{}\": missing scheme (e.g. http://)", url)),
+        };
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(index) if rest[index..].starts_with('?') => (&rest[..index], format!("/{}", &rest[index..])),
+            Some(index) => (&rest[..index], rest[index..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+        let path = path.split('#').next().unwrap_or("/").to_string();
+        // An IPv6 address is enclosed in brackets, since it contains colons itself (e.g. `[::1]:8080`)
+        let invalid_port = || anyhow!("Invalid url \"{}\": invalid port", url);
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(rest) => {
+                let (host, rest) = rest.split_once(']')
+                    .ok_or_else(|| anyhow!("Invalid url \"{}\": unclosed IPv6 address", url))?;
+                if !rest.is_empty() && !rest.starts_with(':') {
+                    return Err(invalid_port());
+                }
+                (host, rest.strip_prefix(':'))
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, _)) if host.contains(':') => {
+                    return Err(anyhow!("Invalid url \"{}\": IPv6 address must be enclosed in brackets", url));
+                }
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid_port())?,
+            None => Self::default_port(tls),
+        };
+        if host.is_empty() {
+            return Err(anyhow!("Invalid url \"{}\": missing host", url));
+        }
+        Ok(Url { tls, host: host.to_string(), port, path })
+    }
+
+    fn default_port(tls: bool) -> u16 {
+        if tls { 443 } else { 80 }
+    }
+
+    /// Value of the host header
+    fn host_header(&self) -> String {
+        let host = if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        if self.port == Self::default_port(self.tls) { host } else { format!("{}:{}", host, self.port) }
+    }
+}
+
+/// Connection to the server, either plain or secured with TLS
+enum Connection {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Connection {
+    /// Wraps the stream with TLS if the url requires it (the handshake is done on the first write)
+    fn new(stream: TcpStream, url: &Url) -> Result<Self> {
+        if !url.tls {
+            return Ok(Connection::Plain(stream));
+        }
+        let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = ServerName::try_from(url.host.clone())
+            .map_err(|_| anyhow!("Invalid host \"{}\"", url.host))?;
+        let connection = ClientConnection::new(Arc::new(config), server_name)
+            .map_err(|e| anyhow!("Failed to set up TLS for {}: {}", url.host_header(), e))?;
+        Ok(Connection::Tls(Box::new(StreamOwned::new(connection, stream))))
+    }
+
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Connection::Plain(stream) => stream,
+            Connection::Tls(stream) => stream.get_ref(),
+        }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.read(buf),
+            Connection::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.write(buf),
+            Connection::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Plain(stream) => stream.flush(),
+            Connection::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub reason: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses a raw HTTP/1.x response.
+    /// The interim (`1xx`) responses sent before the final one are skipped.
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let head_end = raw.windows(4).position(|window| window == b"\r\n\r\n")
+            .ok_or_else(|| anyhow!("Invalid response: incomplete headers"))?;
+        let head = String::from_utf8_lossy(&raw[..head_end]);
+        let mut lines = head.split("\r\n");
+        let status_line = lines.next().unwrap_or_default();
+        let mut parts = status_line.splitn(3, ' ');
+        let status = match (parts.next(), parts.next()) {
+            (Some(version), Some(status)) if version.starts_with("HTTP/") => status.parse()
+                .map_err(|_| anyhow!("Invalid response status line: {}", status_line))?,
+            _ => return Err(anyhow!("Invalid response status line: {}", status_line)),
+        };
+        if (100..200).contains(&status) {
+            return Self::parse(&raw[head_end + 4..]);
+        }
+        let reason = parts.next().unwrap_or_default().to_string();
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect();
+
+        let mut response = Response { status, reason, headers, body: Vec::new() };
+        let body = &raw[head_end + 4..];
+        response.body = if response.header("transfer-encoding").map(|e| e.eq_ignore_ascii_case("chunked")).unwrap_or(false) {
+            decode_chunked(body)?
+        } else if let Some(length) = response.header("content-length").and_then(|length| length.parse().ok()) {
+            body[..body.len().min(length)].to_vec()
+        } else {
+            body.to_vec()
+        };
+        Ok(response)
+    }
+}
+
+/// Decodes a body sent with the chunked transfer encoding
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut result = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|window| window == b"\r\n")
+            .ok_or_else(|| anyhow!("Invalid response: incomplete chunk"))?;
+        let size = String::from_utf8_lossy(&body[..line_end]);
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or_default().trim(), 16)
+            .map_err(|_| anyhow!("Invalid response: invalid chunk size \"{}\"", size))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(result);
+        }
+        if body.len() < size {
+            return Err(anyhow!("Invalid response: incomplete chunk"));
+        }
+        result.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+/// Error of a request which did not complete in time
+#[derive(Debug)]
+pub struct TimeoutError;
+
+impl std::fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Request timed out")
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+/// Sends the request and waits for the whole response (the connection is closed afterwards).
+/// If the request does not complete within the timeout, a `TimeoutError` is returned.
+pub fn send(request: &Request, timeout: Option<Duration>) -> Result<Response> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let remaining = || match deadline {
+        Some(deadline) => deadline.checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .map(Some)
+            .ok_or(TimeoutError),
+        None => Ok(None),
+    };
+
+    let url = &request.url;
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", request.method, url.path, url.host_header());
+    let has_header = |name: &str| request.headers.iter().any(|(key, _)| key.eq_ignore_ascii_case(name));
+    if !has_header("user-agent") {
+        head.push_str(&format!("User-Agent: nauman/{}\r\n", env!("CARGO_PKG_VERSION")));
+    }
+    for (key, value) in &request.headers {
+        // A line break would end the header and inject arbitrary headers (or a body) into the request
+        if key.contains(['\r', '\n']) || value.contains(['\r', '\n']) {
+            return Err(anyhow!("Invalid header \"{}\": header names and values can not contain line breaks", key.escape_debug()));
+        }
+        head.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    if !request.body.is_empty() || !matches!(request.method.as_str(), "GET" | "HEAD" | "DELETE" | "OPTIONS") {
+        head.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
+    }
+    head.push_str("Connection: close\r\n\r\n");
+
+    let addresses = (url.host.as_str(), url.port).to_socket_addrs()
+        .map_err(|e| anyhow!("Failed to resolve host \"{}\": {}", url.host, e))?;
+    let mut connection = Err(anyhow!("Failed to resolve host \"{}\"", url.host));
+    for address in addresses {
+        let result = match remaining()? {
+            Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+            None => TcpStream::connect(address),
+        };
+        match result {
+            Ok(stream) => {
+                connection = Ok(stream);
+                break;
+            }
+            Err(e) if is_timeout(&e) => return Err(TimeoutError.into()),
+            Err(e) => connection = Err(anyhow!("Failed to connect to {}: {}", url.host_header(), e)),
+        }
+    }
+    let mut stream = Connection::new(connection?, url)?;
+
+
+    let io_error = |e: io::Error| if is_timeout(&e) {
+        anyhow::Error::from(TimeoutError)
+    } else {
+        anyhow!("Request to {} failed: {}", url.host_header(), e)
+    };
+    stream.tcp().set_write_timeout(remaining()?).map_err(io_error)?;
+    stream.tcp().set_read_timeout(remaining()?).map_err(io_error)?;
+    let mut message = head.into_bytes();
+    message.extend_from_slice(&request.body);
+    stream.write_all(&message).and_then(|_| stream.flush()).map_err(io_error)?;
+
+    let mut raw = Vec::new();
+    let mut buffer = [0; 8192];
+    loop {
+        stream.tcp().set_read_timeout(remaining()?).map_err(io_error)?;
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(size) => raw.extend_from_slice(&buffer[..size]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            // Servers commonly close TLS connections without notifying the client
+            Err(e) if url.tls && e.kind() == io::ErrorKind::UnexpectedEof && !raw.is_empty() => break,
+            Err(e) => return Err(io_error(e)),
+        }
+    }
+    Response::parse(&raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+    use test_case::test_case;
+    use crate::http::{send, Request, Response, TimeoutError, Url};
+
+    #[test_case("http://localhost:8080/ping?id=1", false, "localhost", 8080, "/ping?id=1" ; "full")]
+    #[test_case("http://example.com", false, "example.com", 80, "/" ; "no path")]
+    #[test_case("http://example.com?id=1#top", false, "example.com", 80, "/?id=1" ; "query without path")]
+    #[test_case("https://example.com/api", true, "example.com", 443, "/api" ; "https")]
+    #[test_case("https://example.com:8443", true, "example.com", 8443, "/" ; "https with port")]
+    #[test_case("http://[::1]:8080/ping", false, "::1", 8080, "/ping" ; "ipv6 with port")]
+    #[test_case("https://[2001:db8::1]", true, "2001:db8::1", 443, "/" ; "ipv6")]
+    fn test_parse_url(url: &str, tls: bool, host: &str, port: u16, path: &str) {
+        assert_eq!(Url::parse(url).unwrap(), Url { tls, host: host.to_string(), port, path: path.to_string() });
+    }
+
+    #[test_case("ftp://example.com" ; "scheme")]
+    #[test_case("example.com" ; "missing scheme")]
+    #[test_case("http://:80/" ; "missing host")]
+    #[test_case("http://[::1/" ; "unclosed ipv6")]
+    #[test_case("http://[::1]8080/" ; "ipv6 port without colon")]
+    #[test_case("http://::1:8080/" ; "ipv6 without brackets")]
+    fn test_parse_invalid_url(url: &str) {
+        assert!(Url::parse(url).is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = Response::parse(b"HTTP/1.1 404 Not Found\r\nContent-Length: 5\r\n\r\nerror").unwrap();
+        assert_eq!((response.status, response.reason.as_str(), response.body.as_slice()), (404, "Not Found", &b"error"[..]));
+
+        let response = Response::parse(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n").unwrap();
+        assert_eq!(response.body, b"Wikipedia");
+    }
+
+    #[test]
+    fn test_parse_interim_response() {
+        let raw = b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 103 Early Hints\r\nLink: </style.css>\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone";
+        let response = Response::parse(raw).unwrap();
+        assert_eq!((response.status, response.reason.as_str(), response.body.as_slice()), (200, "OK", &b"done"[..]));
+        assert_eq!(response.header("link"), None);
+    }
+
+    #[test]
+    fn test_host_header_ipv6() {
+        assert_eq!(Url::parse("http://[::1]:8080/").unwrap().host_header(), "[::1]:8080");
+        assert_eq!(Url::parse("http://[::1]/").unwrap().host_header(), "[::1]");
+    }
+
+    #[test_case("X-Token", "abc\r\nX-Injected: 1" ; "value")]
+    #[test_case("X-Token\nX-Injected", "1" ; "name")]
+    fn test_send_header_injection(key: &str, value: &str) {
+        // The request is rejected before connecting, the port is never listened on
+        let request = Request {
+            method: "GET".to_string(),
+            url: Url::parse("http://127.0.0.1:9/").unwrap(),
+            headers: vec![(key.to_string(), value.to_string())],
+            body: Vec::new(),
+        };
+        let error = send(&request, Some(Duration::from_secs(5))).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid header"), "{}", error);
+    }
+
+    #[test]
+    fn test_send() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !String::from_utf8_lossy(&request).ends_with("{\"ok\":true}") {
+                let size = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..size]);
+            }
+            stream.write_all(b"HTTP/1.1 201 Created\r\nContent-Length: 7\r\n\r\ncreated").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let request = Request {
+            method: "POST".to_string(),
+            url: Url::parse(&format!("http://127.0.0.1:{}/items", port)).unwrap(),
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: b"{\"ok\":true}".to_vec(),
+        };
+        let response = send(&request, Some(Duration::from_secs(5))).unwrap();
+        let received = server.join().unwrap();
+        assert_eq!(response.status, 201);
+        assert_eq!(response.body, b"created");
+        assert!(received.starts_with(&format!("POST /items HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n", port)));
+        assert!(received.contains("Content-Type: application/json\r\nContent-Length: 11\r\n"));
+    }
+
+    #[test]
+    fn test_send_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let request = Request {
+            method: "GET".to_string(),
+            url: Url::parse(&format!("http://127.0.0.1:{}/", listener.local_addr().unwrap().port())).unwrap(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        let error = send(&request, Some(Duration::from_millis(200))).unwrap_err();
+        assert!(error.is::<TimeoutError>());
+    }
+
+    #[test]
+    fn test_send_tls_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]);
+            let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+        });
+
+        let request = Request {
+            method: "GET".to_string(),
+            url: Url::parse(&format!("https://127.0.0.1:{}/", port)).unwrap(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        let error = send(&request, Some(Duration::from_secs(5))).unwrap_err();
+        server.join().unwrap();
+        assert!(error.to_string().starts_with(&format!("Request to 127.0.0.1:{} failed", port)), "{}", error);
+    }
+}
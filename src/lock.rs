@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use nix::fcntl::{flock, FlockArg};
+
+/// Interval between the attempts to acquire a lock while waiting with a timeout
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Exclusive advisory lock on a file which is released when dropped
+#[derive(Debug)]
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    fn open(path: &Path) -> Result<File> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::OpenOptions::new().create(true).write(true).truncate(false).open(path)
+            .map_err(|e| anyhow!("Failed to open lock file: {:?}. Error: {}", path, e))
+    }
+
+    /// Acquires the lock without blocking. Returns none if the lock is held by another process.
+    pub fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        let file = Self::open(path)?;
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => Ok(Some(FileLock { file })),
+            Err(nix::Error::EWOULDBLOCK) => Ok(None),
+            Err(e) => Err(anyhow!("Failed to lock file: {:?}. Error: {}", path, e)),
+        }
+    }
+
+    /// Acquires the lock waiting for at most the given timeout (or indefinitely if none).
+    /// Returns none if the lock could not be acquired in time.
+    pub fn acquire(path: &Path, timeout: Option<Duration>) -> Result<Option<Self>> {
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => {
+                let file = Self::open(path)?;
+                flock(file.as_raw_fd(), FlockArg::LockExclusive)
+                    .map_err(|e| anyhow!("Failed to lock file: {:?}. Error: {}", path, e))?;
+                return Ok(Some(FileLock { file }));
+            }
+        };
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(lock) = Self::try_acquire(path)? {
+                return Ok(Some(lock));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            std::thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // The lock is also released when the file is closed, so errors can be ignored
+        let _ = flock(self.file.as_raw_fd(), FlockArg::Unlock);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::lock::FileLock;
+    use crate::utils::tmpfile;
+
+    #[test]
+    fn test_exclusive_lock() {
+        let path = tmpfile(&std::env::temp_dir(), "nauman", ".lock").unwrap();
+
+        let lock = FileLock::try_acquire(&path).unwrap().expect("Lock should be free");
+        assert!(FileLock::try_acquire(&path).unwrap().is_none());
+        assert!(FileLock::acquire(&path, Some(Duration::from_millis(200))).unwrap().is_none());
+
+        drop(lock);
+        assert!(FileLock::try_acquire(&path).unwrap().is_some());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,22 +1,98 @@
 use std::io;
 use std::io::Write;
-use std::path::PathBuf;
-use crate::{execution::ExecutionContext, config::{LogHandlers, Shell}, logging::{MultiOutputStream, LoggingSpec, pprint}, common::Env, flow::Command, flow};
+use std::path::Path;
+use crate::{execution::ExecutionContext, config::{HttpRequest, LockBehavior, LogHandler, LogHandlers}, logging::{InputStream, LineDecoration, MultiOutputStream, OutputStream, LoggingSpec, OutputStreamSpec, PipeSpec, pprint}, common::Env, flow::Command, flow};
 use anyhow::{Result};
 use colored::{Colorize};
 use prettytable::{Cell, row, Row, Table};
 use crate::common::LogLevel;
-use crate::execution::ExecutionResult;
+use crate::execution::{ExecutionResult, JobResult, RunInfo};
 use crate::pprint::{flex_banner, truncate_string};
+use crate::retention::RetentionAction;
+use crate::http;
+use serde_json::{json, Value};
 
 pub trait LogAction {
     fn min_level(&self) -> LogLevel;
 
     fn write(&self, level: LogLevel, output: &mut impl std::io::Write) -> std::io::Result<()>;
+
+    /// Structured representation of the action written to the event (json) outputs
+    fn event(&self) -> Option<Value> {
+        None
+    }
+
+    /// Whether the action marks the start or the end of a job.
+    /// Its event is written to the event outputs regardless of their level.
+    fn is_lifecycle(&self) -> bool {
+        false
+    }
+}
+
+/// Name of the event for a command (task or hook)
+fn command_event(command: &Command, event: &str) -> String {
+    format!("{}_{}", if command.is_hook { "hook" } else { "task" }, event)
+}
+
+pub struct ActionJobStart<'a> {
+    pub flow: &'a flow::Flow,
+    pub run: &'a RunInfo,
+}
+
+impl<'a> LogAction for ActionJobStart<'a> {
+    fn min_level(&self) -> LogLevel {
+        LogLevel::Info
+    }
+
+    fn write(&self, _level: LogLevel, _output: &mut impl Write) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn event(&self) -> Option<Value> {
+        Some(json!({
+            "event": "job_start",
+            "job_name": self.flow.name,
+            "trigger": self.run.trigger,
+        }))
+    }
+
+    fn is_lifecycle(&self) -> bool {
+        true
+    }
+}
+
+pub struct ActionJobEnd<'a> {
+    pub flow: &'a flow::Flow,
+    pub result: &'a JobResult,
+}
+
+impl<'a> LogAction for ActionJobEnd<'a> {
+    fn min_level(&self) -> LogLevel {
+        LogLevel::Info
+    }
+
+    fn write(&self, _level: LogLevel, _output: &mut impl Write) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn event(&self) -> Option<Value> {
+        Some(json!({
+            "event": "job_end",
+            "job_name": self.flow.name,
+            "status": self.result.status,
+            "exit_code": self.result.exit_code,
+        }))
+    }
+
+    fn is_lifecycle(&self) -> bool {
+        true
+    }
 }
 
 pub struct ActionCommandStart<'a> {
-    pub command: &'a Command
+    pub command: &'a Command,
+    pub attempt: u32,
+    pub max_attempts: u32,
 }
 
 impl<'a> LogAction for ActionCommandStart<'a> {
@@ -25,20 +101,131 @@ impl<'a> LogAction for ActionCommandStart<'a> {
     }
 
     fn write(&self, _level: LogLevel, output: &mut impl std::io::Write) -> std::io::Result<()> {
+        let name = if self.max_attempts > 1 {
+            format!("{} (attempt {}/{})", &self.command.name, self.attempt, self.max_attempts)
+        } else {
+            self.command.name.clone()
+        };
         writeln!(output, "{}", if self.command.is_hook {
-            pprint::flex_banner(format!("Hook: {}", &self.command.name)).yellow()
+            pprint::flex_banner(format!("Hook: {}", name)).yellow()
         } else {
-            pprint::flex_banner( format!("Task: {}", &self.command.name)).green()
+            pprint::flex_banner( format!("Task: {}", name)).green()
         })
     }
+
+    fn event(&self) -> Option<Value> {
+        Some(json!({
+            "event": command_event(self.command, "start"),
+            "name": self.command.name,
+            "attempt": self.attempt,
+            "max_attempts": self.max_attempts,
+        }))
+    }
+}
+
+pub struct ActionCommandRetry<'a> {
+    pub command: &'a Command,
+    pub result: &'a ExecutionResult,
+    pub max_attempts: u32,
+    pub delay: std::time::Duration,
+}
+
+impl<'a> LogAction for ActionCommandRetry<'a> {
+    fn min_level(&self) -> LogLevel {
+        LogLevel::Warn
+    }
+
+    fn write(&self, _level: LogLevel, output: &mut impl Write) -> std::io::Result<()> {
+        writeln!(output, "{}", pprint::task_retry(
+            &self.command.name, self.result.exit_code, self.result.attempts, self.max_attempts, &self.delay
+        ))
+    }
+
+    fn event(&self) -> Option<Value> {
+        Some(json!({
+            "event": command_event(self.command, "retry"),
+            "name": self.command.name,
+            "exit_code": self.result.exit_code,
+            "attempt": self.result.attempts,
+            "max_attempts": self.max_attempts,
+            "delay": self.delay.as_secs_f64(),
+        }))
+    }
+}
+
+pub struct ActionJobLocked<'a> {
+    pub flow: &'a flow::Flow,
+    pub path: &'a Path,
+    pub behavior: LockBehavior,
+}
+
+impl<'a> LogAction for ActionJobLocked<'a> {
+    fn min_level(&self) -> LogLevel {
+        LogLevel::Warn
+    }
+
+    fn write(&self, _level: LogLevel, output: &mut impl Write) -> std::io::Result<()> {
+        writeln!(output, "{}", pprint::job_locked(&self.flow.name, self.path, self.behavior))
+    }
+}
+
+pub struct ActionJobResumed<'a> {
+    pub flow: &'a flow::Flow,
+    pub run_dir: &'a Path,
+    /// Number of the restored tasks
+    pub restored: usize,
+    /// Number of the tasks executed in the previous run
+    pub total: usize,
+}
+
+impl<'a> LogAction for ActionJobResumed<'a> {
+    fn min_level(&self) -> LogLevel {
+        LogLevel::Info
+    }
+
+    fn write(&self, _level: LogLevel, output: &mut impl Write) -> std::io::Result<()> {
+        writeln!(output, "{}", pprint::job_resumed(&self.flow.name, self.run_dir, self.restored, self.total))
+    }
+
+    fn event(&self) -> Option<Value> {
+        Some(json!({
+            "event": "job_resumed",
+            "run_dir": self.run_dir,
+            "restored": self.restored,
+            "total": self.total,
+        }))
+    }
+}
+
+pub struct ActionRunPruned<'a> {
+    pub path: &'a Path,
+    pub action: RetentionAction,
+    pub dry_run: bool,
+}
+
+impl<'a> LogAction for ActionRunPruned<'a> {
+    fn min_level(&self) -> LogLevel {
+        LogLevel::Info
+    }
+
+    fn write(&self, _level: LogLevel, output: &mut impl Write) -> std::io::Result<()> {
+        writeln!(output, "{}", pprint::run_pruned(self.path, self.action, self.dry_run))
+    }
+
+    fn event(&self) -> Option<Value> {
+        Some(json!({
+            "event": "run_pruned",
+            "path": self.path,
+            "action": self.action.to_string(),
+            "dry_run": self.dry_run,
+        }))
+    }
 }
 
 pub struct ActionShell<'a> {
-    pub handler: &'a Shell,
-    pub cwd: &'a PathBuf,
+    /// The command as it is announced
+    pub command: &'a str,
     pub env: &'a Env,
-    pub shell_program: &'a str,
-    pub shell_args: &'a [String],
 }
 
 impl<'a> LogAction for ActionShell<'a> {
@@ -48,12 +235,74 @@ impl<'a> LogAction for ActionShell<'a> {
 
     fn write(&self, level: LogLevel, output: &mut impl std::io::Write) -> std::io::Result<()> {
         if level >= LogLevel::Info {
-            writeln!(output, "{}", pprint::command(&self.handler.run))?;
+            writeln!(output, "{}", pprint::command(self.command))?;
+        }
+        // Dump the variables set by the job (secrets are masked by the outputs)
+        if level >= LogLevel::Debug {
+            let mut env: Vec<(String, String)> = self.env.difference(&Env::from_system()).into_iter().collect();
+            env.sort();
+            for (name, value) in env {
+                writeln!(output, "{}", pprint::env_var(&name, &value))?;
+            }
         }
         Ok(())
     }
 }
 
+pub struct ActionHttp<'a> {
+    pub request: &'a HttpRequest,
+}
+
+impl<'a> LogAction for ActionHttp<'a> {
+    fn min_level(&self) -> LogLevel {
+        LogLevel::Info
+    }
+
+    fn write(&self, level: LogLevel, output: &mut impl std::io::Write) -> std::io::Result<()> {
+        if level >= LogLevel::Info {
+            writeln!(output, "{}", pprint::http_request(&self.request.method.to_uppercase(), &self.request.url))?;
+        }
+        if level >= LogLevel::Debug {
+            for (name, value) in &self.request.headers {
+                writeln!(output, "{}", pprint::http_header(name, value))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct ActionHttpResponse<'a> {
+    pub response: &'a http::Response,
+    /// Whether the response status indicates a success
+    pub expected: bool,
+}
+
+impl<'a> LogAction for ActionHttpResponse<'a> {
+    fn min_level(&self) -> LogLevel {
+        LogLevel::Info
+    }
+
+    fn write(&self, level: LogLevel, output: &mut impl std::io::Write) -> std::io::Result<()> {
+        if level >= LogLevel::Info {
+            writeln!(output, "{}", pprint::http_response(self.response.status, &self.response.reason, self.expected))?;
+        }
+        if level >= LogLevel::Debug {
+            for (name, value) in &self.response.headers {
+                writeln!(output, "{}", pprint::http_header(name, value))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn event(&self) -> Option<Value> {
+        Some(json!({
+            "event": "http_response",
+            "status": self.response.status,
+            "expected": self.expected,
+        }))
+    }
+}
+
 pub struct ActionCommandEnd<'a> {
     pub command: &'a Command,
     pub result: &'a ExecutionResult,
@@ -65,30 +314,48 @@ impl<'a> LogAction for ActionCommandEnd<'a> {
     }
 
     fn write(&self, level: LogLevel, output: &mut impl Write) -> std::io::Result<()> {
-        if !self.result.is_success() && !self.result.is_aborted() && level >= LogLevel::Error {
+        if self.result.is_timed_out() && level >= LogLevel::Error {
+            writeln!(output, "{}", pprint::task_timeout(
+                &self.command.name, self.result.duration.as_ref()
+            ))?;
+        } else if !self.result.is_success() && !self.result.is_aborted() && level >= LogLevel::Error {
             writeln!(output, "{}", pprint::task_error(
                 &self.command.name, self.result.exit_code, self.result.duration.as_ref()
             ))?;
         }
-        if self.result.is_aborted() && level >= LogLevel::Debug {
-            if !self.command.is_hook {
-                writeln!(output, "{}", pprint::task_aborted(
-                    &self.command.name, self.command.policy
-                ))?;
-            }
+        if self.result.is_aborted() && level >= LogLevel::Debug && !self.command.is_hook {
+            writeln!(output, "{}", pprint::task_aborted(
+                &self.command.name, self.command.policy
+            ))?;
         }
-        if self.result.is_success() && !self.result.is_aborted() && level >= LogLevel::Debug {
+        if self.result.is_skipped() && level >= LogLevel::Info {
+            writeln!(output, "{}", pprint::task_skipped(
+                &self.command.name, self.command.condition.as_ref()
+            ))?;
+        }
+        if self.result.is_success() && !self.result.is_aborted() && !self.result.is_skipped() && level >= LogLevel::Debug {
             writeln!(output, "{}", pprint::task_success(
                 &self.command.name, self.result.duration.as_ref()
             ))?;
         }
         Ok(())
     }
+
+    fn event(&self) -> Option<Value> {
+        Some(json!({
+            "event": command_event(self.command, "end"),
+            "name": self.command.name,
+            "status": self.result.status(),
+            "exit_code": self.result.exit_code,
+            "attempts": self.result.attempts,
+            "duration": self.result.duration.map(|duration| duration.as_secs_f64()),
+        }))
+    }
 }
 
 pub struct ActionSummary<'a> {
     pub flow: &'a flow::Flow,
-    pub results: &'a Vec<(String, ExecutionResult)>,
+    pub result: &'a JobResult,
 }
 
 impl<'a> LogAction for ActionSummary<'a> {
@@ -96,47 +363,110 @@ impl<'a> LogAction for ActionSummary<'a> {
         LogLevel::Info
     }
 
-    fn write(&self, _level: LogLevel, _output: &mut impl Write) -> std::io::Result<()> {
+    fn write(&self, _level: LogLevel, output: &mut impl Write) -> std::io::Result<()> {
         let mut table = Table::new();
         table.add_row(row![
             "Task",
             "Action",
             "Time (in s)",
+            "Attempts",
         ]);
 
-        for (command_id, result) in self.results.iter() {
+        for (command_id, result) in self.result.results.iter() {
             let command = self.flow.command(command_id).expect("Command not found");
-            let step = if result.is_success() {
+            let step = if !result.is_skipped() && result.is_success() {
                 if command.is_hook { "🪝".to_string() } else { command.task_no.map(|i| i.to_string()).unwrap_or_default() }
-            } else if result.is_aborted() { "⛔️".to_string() } else { "💥".to_string() };
-            let name = &truncate_string(&command.name, 60);
-            let duration = result.duration.map(|d| d.as_secs().to_string())
-                .unwrap_or_else(|| "-".to_string());
-
-            table.add_row(Row::new(vec![
-                Cell::new(&step),
-                Cell::new(name).style_spec(if !result.is_success() { "Fr" } else { "" }),
-                Cell::new(&duration),
-            ]));
+            } else {
+                summary_status(result).to_string()
+            };
+            table.add_row(summary_row(step, result.name.as_ref().unwrap_or(&command.name), result));
+            add_nested_rows(&mut table, &result.nested, 1);
         }
 
-        println!("{}", flex_banner(format!("Summary: {}", self.flow.name)).yellow());
-        table.printstd();
+        if self.result.is_success() {
+            writeln!(output, "{}", flex_banner(format!("Summary: {}", self.flow.name)).yellow())?;
+        } else {
+            writeln!(output, "{}", flex_banner(format!("Summary: {} (failed)", self.flow.name)).red())?;
+        }
+        table.print(output)?;
         Ok(())
     }
 }
 
+/// Returns the summary symbol of an unsuccessful (or skipped) command
+fn summary_status(result: &ExecutionResult) -> &'static str {
+    if result.is_deselected() { "➖" } else if result.is_skipped() { "⏭️" } else if result.is_aborted() { "⛔️" }
+    else if result.is_timed_out() { "⏰" } else if !result.is_success() { "💥" } else { "" }
+}
+
+fn summary_row(step: String, name: &str, result: &ExecutionResult) -> Row {
+    let duration = result.duration.map(|d| d.as_secs().to_string())
+        .unwrap_or_else(|| "-".to_string());
+    Row::new(vec![
+        Cell::new(&step),
+        Cell::new(&truncate_string(name, 60)).style_spec(if !result.is_success() { "Fr" } else { "" }),
+        Cell::new(&duration),
+        Cell::new(&if result.attempts > 0 { result.attempts.to_string() } else { "-".to_string() }),
+    ])
+}
+
+/// Adds the results of the sub job commands below the task running them (indented by their depth)
+fn add_nested_rows(table: &mut Table, results: &[ExecutionResult], depth: usize) {
+    for result in results {
+        let name = format!("{}↳ {}", "  ".repeat(depth - 1), result.name.as_ref().unwrap_or(&result.command_id));
+        table.add_row(summary_row(summary_status(result).to_string(), &name, result));
+        add_nested_rows(table, &result.nested, depth + 1);
+    }
+}
+
 pub struct Logger {
     config: LogHandlers,
     level: LogLevel,
+    /// Prefix for the console and file output lines (used to distinguish parallel tasks)
+    prefix: Option<String>,
     pub output: MultiOutputStream,
 }
 
 impl Logger {
     pub fn new(config: LogHandlers, level: LogLevel) -> Logger {
+        // Log job level messages to the console until switched to a command
+        let console = LoggingSpec {
+            pipes: vec![PipeSpec {
+                input: InputStream::Both,
+                output: OutputStreamSpec::Stdout,
+                decoration: LineDecoration {
+                    strip_ansi: !LogHandler::default_console().ansi(),
+                    ..LineDecoration::default()
+                },
+                level: None,
+                internal: true,
+            }],
+        };
         Logger {
             config,
             level,
+            prefix: None,
+            output: MultiOutputStream::from_spec(console, None).expect("Failed to open the console output"),
+        }
+    }
+
+    /// Creates a new logger with the same configuration for a parallel task
+    pub fn fork(&self, task_id: Option<String>) -> Logger {
+        Logger {
+            config: self.config.clone(),
+            level: self.level,
+            prefix: task_id.map(|id| format!("[{}] ", id)),
+            output: MultiOutputStream::new(),
+        }
+    }
+
+    /// Creates a new logger with the same configuration for a sub job run by the given task.
+    /// The console and file lines of the sub job are prefixed with the id of the task (after the own prefix).
+    pub fn nest(&self, task_id: &str) -> Logger {
+        Logger {
+            config: self.config.clone(),
+            level: self.level,
+            prefix: Some(format!("{}[{}] ", self.prefix.as_deref().unwrap_or_default(), task_id)),
             output: MultiOutputStream::new(),
         }
     }
@@ -146,7 +476,8 @@ impl Logger {
         context: &ExecutionContext,
     ) -> Result<()> {
         let spec = LoggingSpec::from_config(&self.config, context)?;
-        self.output = MultiOutputStream::from_spec(spec);
+        self.output.flush()?;
+        self.output = MultiOutputStream::from_spec(spec, self.prefix.as_deref())?;
         Ok(())
     }
 
@@ -158,10 +489,68 @@ impl Logger {
         self.output.flush()
     }
 
+    /// Writes the action to every handler which logs internal messages at its level.
+    /// The event outputs receive the structured representation of the action instead,
+    /// the job lifecycle events are written to them at any level.
     pub fn log_action(&mut self, action: impl LogAction) -> Result<()> {
-        if self.level >= action.min_level() {
-            action.write(self.level, self.mut_output())?;
+        let event = action.event();
+        for pipe in self.output.pipes.iter_mut().filter(|pipe| pipe.internal) {
+            let level = pipe.level.unwrap_or(self.level);
+            let enabled = level >= action.min_level();
+            match &mut pipe.output {
+                OutputStream::Json(json) => if let Some(event) = event.as_ref().filter(|_| enabled || action.is_lifecycle()) {
+                    json.write_event(event)?;
+                },
+                output => if enabled {
+                    action.write(level, output)?;
+                },
+            }
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::LogLevel;
+    use crate::config;
+    use crate::execution::{ExecutionResult, JobResult, JobStatus};
+    use crate::flow::Flow;
+    use crate::logging::{ActionSummary, LogAction};
+
+    #[test]
+    fn test_summary_nested() {
+        let job: config::Job = serde_yaml::from_str(r#"
+name: test
+tasks:
+  - id: backup
+    name: Backup
+    job: ${{ env.BACKUP_JOB }}
+"#).unwrap();
+        let flow = Flow::parse(&job).unwrap();
+        let nested = |id: &str, exit_code: i32| ExecutionResult {
+            name: Some(format!("Nested {}", id)),
+            exit_code,
+            attempts: 1,
+            ..ExecutionResult::new(id.to_string(), None)
+        };
+        let result = JobResult {
+            status: JobStatus::Failed,
+            exit_code: 3,
+            results: vec![("backup".to_string(), ExecutionResult {
+                exit_code: 3,
+                attempts: 1,
+                nested: vec![nested("dump", 0), ExecutionResult { nested: vec![nested("upload", 3)], ..nested("verify", 3) }],
+                ..ExecutionResult::new("backup".to_string(), None)
+            })],
+        };
+
+        let mut output = Vec::new();
+        ActionSummary { flow: &flow, result: &result }.write(LogLevel::Info, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("| 💥   | Backup"), "{}", output);
+        assert!(output.contains("|      | ↳ Nested dump"), "{}", output);
+        assert!(output.contains("| 💥   | ↳ Nested verify"), "{}", output);
+        assert!(output.contains("| 💥   |   ↳ Nested upload"), "{}", output);
+    }
+}
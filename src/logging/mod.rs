@@ -2,8 +2,9 @@ pub mod stream;
 pub mod spec;
 pub mod pprint;
 pub mod logger;
+pub mod rotation;
 
 pub use stream::*;
 pub use spec::*;
-pub use pprint::*;
-pub use logger::*;
\ No newline at end of file
+pub use logger::*;
+pub use rotation::*;
\ No newline at end of file
@@ -1,5 +1,7 @@
 use colored::*;
-use crate::config::ExecutionPolicy;
+use crate::config::{ExecutionPolicy, LockBehavior};
+use crate::expression::Condition;
+use crate::retention::RetentionAction;
 
 
 const BANNER_CHAR: &str = "-";
@@ -20,6 +22,43 @@ pub fn command(text: &str) -> colored::ColoredString {
     format!("$ {}", text).cyan()
 }
 
+pub fn http_request(method: &str, url: &str) -> colored::ColoredString {
+    format!("> {} {}", method, url).cyan()
+}
+
+pub fn http_response(status: u16, reason: &str, expected: bool) -> colored::ColoredString {
+    let text = format!("< {} {}", status, reason);
+    if expected { text.green() } else { text.red() }
+}
+
+pub fn http_header(name: &str, value: &str) -> colored::ColoredString {
+    format!("  {}: {}", name, value).dimmed()
+}
+
+/// Formats a program and its arguments as a command line (arguments with whitespace are quoted)
+pub fn command_line(program: &str, args: &[String]) -> String {
+    std::iter::once(program).chain(args.iter().map(String::as_str))
+        .map(|arg| if arg.is_empty() || arg.contains(char::is_whitespace) {
+            format!("'{}'", arg.replace('\'', "'\\''"))
+        } else {
+            arg.to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn env_var(name: &str, value: &str) -> colored::ColoredString {
+    format!("  {}={}", name, value).dimmed()
+}
+
+pub fn success(text: &str) -> colored::ColoredString {
+    text.green()
+}
+
+pub fn warning(text: &str) -> colored::ColoredString {
+    text.yellow()
+}
+
 pub fn error(text: &str) -> colored::ColoredString {
     text.red()
 }
@@ -39,6 +78,27 @@ pub fn task_error(name: &str, status: i32, duration: Option<&std::time::Duration
 
 }
 
+pub fn task_timeout(name: &str, duration: Option<&std::time::Duration>) -> colored::ColoredString {
+    if let Some(duration) = duration {
+        format!(
+            "Task \"{name}\" timed out after {duration}s and was terminated. This indicates a failure",
+            name=name, duration=duration.as_secs()
+        ).red()
+    } else {
+        format!(
+            "Task \"{name}\" was not started, since the job has timed out. This indicates a failure",
+            name=name
+        ).red()
+    }
+}
+
+pub fn task_retry(name: &str, status: i32, attempt: u32, max_attempts: u32, delay: &std::time::Duration) -> colored::ColoredString {
+    format!(
+        "Task \"{name}\" attempt {attempt}/{max_attempts} failed with exit status: {status}. Retrying in {delay:.1}s",
+        name=name, status=status, attempt=attempt, max_attempts=max_attempts, delay=delay.as_secs_f32()
+    ).yellow()
+}
+
 pub fn task_success(name: &str, duration: Option<&std::time::Duration>) -> colored::ColoredString {
     if let Some(duration) = duration {
         format!(
@@ -76,6 +136,50 @@ pub fn task_aborted(name: &str, policy: ExecutionPolicy) -> colored::ColoredStri
     }
 }
 
+pub fn task_skipped(name: &str, condition: Option<&Condition>) -> colored::ColoredString {
+    if let Some(condition) = condition {
+        format!(
+            "Task \"{name}\" was skipped: Because the condition \"{condition}\" is not met, this task was not executed",
+            name=name, condition=condition
+        ).blue()
+    } else {
+        format!(
+            "Task \"{name}\" was skipped. This task was not executed",
+            name=name
+        ).blue()
+    }
+}
+
+pub fn job_locked(name: &str, path: &std::path::Path, behavior: LockBehavior) -> colored::ColoredString {
+    match behavior {
+        LockBehavior::Wait => format!(
+            "Job \"{name}\" is already running (lock file: {path}). Waiting for it to finish",
+            name=name, path=path.display()
+        ).yellow(),
+        LockBehavior::Skip | LockBehavior::Fail => format!(
+            "Job \"{name}\" is already running (lock file: {path}). This run was skipped",
+            name=name, path=path.display()
+        ).yellow(),
+    }
+}
+
+pub fn job_resumed(name: &str, run_dir: &std::path::Path, restored: usize, total: usize) -> colored::ColoredString {
+    format!(
+        "Resuming job \"{name}\" from {run_dir}. Restored {restored} out of {total} previously executed task(s)",
+        name=name, run_dir=run_dir.display(), restored=restored, total=total
+    ).blue()
+}
+
+pub fn run_pruned(path: &std::path::Path, action: RetentionAction, dry_run: bool) -> colored::ColoredString {
+    let action = match (action, dry_run) {
+        (RetentionAction::Delete, false) => "Deleted",
+        (RetentionAction::Delete, true) => "Would delete",
+        (RetentionAction::Compress, false) => "Compressed",
+        (RetentionAction::Compress, true) => "Would compress",
+    };
+    format!("{action} previous run: {path}", action=action, path=path.display()).blue()
+}
+
 /// Truncates the given string to the given length.
 pub fn truncate_string(text: &str, max_length: usize) -> String {
     if text.len() > max_length {
@@ -0,0 +1,137 @@
+use std::{
+    fs,
+    io,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+};
+use flate2::{write::GzEncoder, Compression};
+use crate::config::FileHandler;
+
+/// Default number of rotated files to keep
+pub const DEFAULT_MAX_FILES: usize = 5;
+
+/// Rotation of a log file once it exceeds its maximum size.
+/// The file is moved to `<file>.1`, the previously rotated files are shifted (`<file>.1` to `<file>.2`, ...)
+/// and the ones exceeding the maximum number of files are removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RotationSpec {
+    /// Maximum size of the file in bytes
+    pub max_size: u64,
+    /// Number of rotated files to keep
+    pub max_files: usize,
+    /// Whether the rotated files are compressed with gzip
+    pub compress: bool,
+}
+
+impl RotationSpec {
+    pub fn from_handler(handler: &FileHandler) -> Option<Self> {
+        handler.max_size.map(|max_size| RotationSpec {
+            max_size: max_size.as_bytes(),
+            max_files: handler.max_files.unwrap_or(DEFAULT_MAX_FILES),
+            compress: handler.compress,
+        })
+    }
+
+    /// Returns the path of the rotated file with the given index
+    pub fn rotated_path(&self, path: &Path, index: usize) -> PathBuf {
+        numbered_path(path, index, self.compress)
+    }
+
+    /// Rotates the file.
+    /// The rotated file is compressed in the background, the returned handle finishes once it is compressed.
+    /// If the compression fails, the rotated file is kept uncompressed and the handle returns the error.
+    pub fn rotate(&self, path: &Path) -> io::Result<Option<JoinHandle<io::Result<()>>>> {
+        if self.max_files == 0 {
+            return fs::remove_file(path).map(|_| None);
+        }
+
+        let oldest = self.rotated_path(path, self.max_files);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for index in (1..self.max_files).rev() {
+            let rotated = self.rotated_path(path, index);
+            if rotated.exists() {
+                fs::rename(&rotated, self.rotated_path(path, index + 1))?;
+            }
+        }
+
+        let rotated = numbered_path(path, 1, false);
+        fs::rename(path, &rotated)?;
+        if !self.compress {
+            return Ok(None);
+        }
+        let compressed = self.rotated_path(path, 1);
+        Ok(Some(thread::spawn(move || {
+            compress_file(&rotated, &compressed).map_err(|e| {
+                let _ = fs::remove_file(&compressed);
+                io::Error::new(e.kind(), format!("Failed to compress rotated log file: {:?}. Error: {}", rotated, e))
+            })
+        })))
+    }
+}
+
+/// Compresses the file with gzip and removes it
+fn compress_file(path: &Path, compressed: &Path) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(fs::File::create(compressed)?, Compression::default());
+    io::copy(&mut fs::File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// Returns the path with the index (and the gzip extension) appended
+fn numbered_path(path: &Path, index: usize, compressed: bool) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", index));
+    if compressed {
+        name.push(".gz");
+    }
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use flate2::read::GzDecoder;
+    use crate::logging::RotationSpec;
+
+    #[test]
+    fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("nauman-rotation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("full.log");
+        let spec = RotationSpec { max_size: 10, max_files: 2, compress: false };
+        for contents in ["first", "second", "third"] {
+            std::fs::write(&path, contents).unwrap();
+            assert!(spec.rotate(&path).unwrap().is_none());
+        }
+
+        let read = |index: usize| std::fs::read_to_string(spec.rotated_path(&path, index)).ok();
+        let (exists, first, second, third) = (path.exists(), read(1), read(2), read(3));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(!exists);
+        assert_eq!(first.as_deref(), Some("third"));
+        assert_eq!(second.as_deref(), Some("second"));
+        assert_eq!(third, None);
+    }
+
+    #[test]
+    fn test_rotate_compressed() {
+        let dir = std::env::temp_dir().join(format!("nauman-rotation-gz-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("full.log");
+        let spec = RotationSpec { max_size: 10, max_files: 2, compress: true };
+        std::fs::write(&path, "contents").unwrap();
+        spec.rotate(&path).unwrap().unwrap().join().unwrap().unwrap();
+
+        let rotated = spec.rotated_path(&path, 1);
+        let mut contents = String::new();
+        GzDecoder::new(std::fs::File::open(&rotated).unwrap()).read_to_string(&mut contents).unwrap();
+        let uncompressed = dir.join("full.log.1").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(rotated.ends_with("full.log.1.gz"));
+        assert_eq!(contents, "contents");
+        assert!(!uncompressed);
+    }
+}
@@ -1,7 +1,11 @@
 use std::path::PathBuf;
-use crate::config::{LogHandlers, LogHandler, LogHandlerType};
+use chrono::{DateTime, Local, NaiveTime};
+use crate::config::{LogHandlers, LogHandler, LogHandlerType, PrefixMode, TimestampMode};
+use crate::common::LogLevel;
 use crate::execution::{ExecutionContext};
+use crate::logging::RotationSpec;
 use anyhow::{format_err, Result};
+use crate::template;
 use crate::utils::resolve_cwd;
 
 
@@ -19,18 +23,15 @@ impl InputStream {
         !matches!(self, InputStream::Stderr)
     }
 
-    #[allow(dead_code)]
     pub fn is_stderr(&self) -> bool {
         !matches!(self, InputStream::Stdout)
     }
 
     pub fn is_compatible(&self, other: Self) -> bool {
-        match (self, other) {
-            (InputStream::Both, _) => true,
-            (InputStream::Stdout, InputStream::Stdout) => true,
-            (InputStream::Stderr, InputStream::Stderr) => true,
-            _ => false,
-        }
+        matches!(
+            (self, other),
+            (InputStream::Both, _) | (InputStream::Stdout, InputStream::Stdout) | (InputStream::Stderr, InputStream::Stderr)
+        )
     }
 }
 
@@ -38,6 +39,19 @@ impl InputStream {
 pub struct FileOutputSpec {
     pub file: PathBuf,
     pub append: bool,
+    /// Rotation of the file once it exceeds its maximum size
+    pub rotation: Option<RotationSpec>,
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonOutputSpec {
+    pub file: PathBuf,
+    /// Fields added to every event (ids of the run and the current task)
+    pub fields: serde_json::Map<String, serde_json::Value>,
+    /// Whether the ansi escape sequences are removed from the output lines
+    pub strip_ansi: bool,
+    /// Secret values masked within the events
+    pub secrets: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -46,12 +60,91 @@ pub enum OutputStreamSpec {
     #[allow(dead_code)]
     Stderr,
     File(FileOutputSpec),
+    Json(JsonOutputSpec),
+}
+
+/// Default name of the json events file (within the log directory)
+const JSON_EVENTS_FILE: &str = "events.jsonl";
+
+/// Renders the templated output path of a log handler
+fn render_output(handler: &LogHandler, output: Option<&String>, context: &ExecutionContext) -> Result<Option<String>> {
+    output
+        .map(|output| template::render(output, context))
+        .transpose()
+        .map_err(|e| format_err!(
+            "Log handler \"{}\" failed to render field \"output\": {}",
+            handler.name.as_deref().unwrap_or(handler.handler.name()), e
+        ))
+}
+
+#[derive(Debug, Clone)]
+pub struct TimestampSpec {
+    pub mode: TimestampMode,
+    /// Format of the timestamps (strftime syntax)
+    pub format: String,
+    /// Time the elapsed time is measured from
+    pub started_at: DateTime<Local>,
+}
+
+/// Decoration prepended to every line written to an output
+#[derive(Debug, Clone, Default)]
+pub struct LineDecoration {
+    pub timestamps: Option<TimestampSpec>,
+    pub prefix: Option<String>,
+    /// Whether the ansi escape sequences are removed from the lines
+    pub strip_ansi: bool,
+    /// Secret values masked within the lines
+    pub secrets: Vec<String>,
+}
+
+impl LineDecoration {
+    pub fn from_handler(handler: &LogHandler, context: &ExecutionContext) -> Self {
+        let options = &handler.options;
+        let timestamps = options.timestamps.map(|mode| TimestampSpec {
+            mode,
+            format: options.timestamp_format.clone().unwrap_or_else(|| mode.default_format().to_string()),
+            started_at: Local::now(),
+        });
+        let prefix = options.prefix.zip(context.current.as_ref())
+            .map(|(mode, (command_id, command))| match mode {
+                PrefixMode::Id => format!("[{}] ", command_id),
+                PrefixMode::Name => format!("[{}] ", command.name),
+            });
+        LineDecoration { timestamps, prefix, strip_ansi: !handler.ansi(), secrets: context.secrets() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_none() && self.prefix.is_none() && !self.strip_ansi && self.secrets.is_empty()
+    }
+
+    /// Renders the decoration of a line started at the given time
+    pub fn render(&self, at: DateTime<Local>) -> String {
+        let mut result = String::new();
+        if let Some(timestamps) = &self.timestamps {
+            let timestamp = match timestamps.mode {
+                TimestampMode::Wall => at.format(&timestamps.format),
+                TimestampMode::Elapsed => (NaiveTime::from_hms(0, 0, 0) + (at - timestamps.started_at))
+                    .format(&timestamps.format),
+            };
+            result.push_str(&format!("{} ", timestamp));
+        }
+        if let Some(prefix) = &self.prefix {
+            result.push_str(prefix);
+        }
+        result
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PipeSpec {
     pub input: InputStream,
     pub output: OutputStreamSpec,
+    /// Decoration of the lines written to the output (not applied to the json outputs)
+    pub decoration: LineDecoration,
+    /// Log level of the internal messages (defaults to the level of the logger)
+    pub level: Option<LogLevel>,
+    /// Whether the internal messages are written to the output
+    pub internal: bool,
 }
 
 impl PipeSpec {
@@ -59,6 +152,7 @@ impl PipeSpec {
         if !handler.options.hooks && context.is_in_hook() {
             return Ok(Vec::new());
         }
+        let decoration = LineDecoration::from_handler(handler, context);
 
         match &handler.handler {
             LogHandlerType::File(f) => {
@@ -67,17 +161,17 @@ impl PipeSpec {
                     return Ok(Vec::new());
                 }
 
-                let mut file = resolve_cwd(&context.log_dir, f.output.as_ref());
+                let output = render_output(handler, f.output.as_ref(), context)?;
+                let mut file = resolve_cwd(&context.log_dir, output.as_ref());
                 // Split logs should be named appropriately
                 if f.split {
                     if file.is_file() {
                         return Err(format_err!("Cannot create directory '{}'", file.display()));
                     }
                     std::fs::create_dir_all(&file)?;
-                    let filename = if context.is_in_hook() {
-                        context.focus.clone().unwrap_or_else(|| "job".to_string())
-                    } else {
-                        context.current_command_id().clone()
+                    let filename = match &context.current {
+                        Some((command_id, _)) if !context.is_in_hook() => command_id.clone(),
+                        _ => context.focus.clone().unwrap_or_else(|| "job".to_string()),
                     };
                     file.push(format!("{}.log", filename));
                 }
@@ -87,13 +181,56 @@ impl PipeSpec {
                     output: OutputStreamSpec::File(FileOutputSpec {
                         file,
                         append: true,
+                        rotation: RotationSpec::from_handler(f),
                     }),
+                    decoration,
+                    level: handler.options.level,
+                    internal: handler.options.internal,
                 }])
             },
             LogHandlerType::Console => {
                 Ok(vec![Self {
                     input,
                     output: OutputStreamSpec::Stdout,
+                    decoration,
+                    level: handler.options.level,
+                    internal: handler.options.internal,
+                }])
+            },
+            LogHandlerType::Json(j) => {
+                // Don't create event files on dry run
+                if context.options.dry_run {
+                    return Ok(Vec::new());
+                }
+
+                let output = render_output(handler, j.output.as_ref(), context)?
+                    .unwrap_or_else(|| JSON_EVENTS_FILE.to_string());
+                let file = resolve_cwd(&context.log_dir, Some(&output));
+                if let Some(parent) = file.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                let mut fields = serde_json::Map::new();
+                fields.insert("job_id".to_string(), context.run.job_id.clone().into());
+                fields.insert("run_id".to_string(), context.run.run_id.clone().into());
+                if let Some((command_id, _)) = &context.current {
+                    fields.insert("task_id".to_string(), command_id.clone().into());
+                }
+                if let Some(focus_id) = &context.focus {
+                    fields.insert("focus_id".to_string(), focus_id.clone().into());
+                }
+
+                Ok(vec![Self {
+                    input,
+                    output: OutputStreamSpec::Json(JsonOutputSpec {
+                        file,
+                        fields,
+                        strip_ansi: !handler.ansi(),
+                        secrets: decoration.secrets,
+                    }),
+                    decoration: LineDecoration::default(),
+                    level: handler.options.level,
+                    internal: handler.options.internal,
                 }])
             },
         }
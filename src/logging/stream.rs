@@ -2,10 +2,17 @@ use std::{
     fs,
     io::{self, BufWriter, Write},
     sync::{Mutex},
-    path::Path,
+    thread::JoinHandle,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
 };
+use anyhow::{format_err, Result};
+use chrono::{DateTime, Local};
+use serde_json::{json, Map, Value};
 use crate::{
-    logging::{InputStream, LoggingSpec, OutputStreamSpec, PipeSpec}
+    common::LogLevel,
+    logging::{FileOutputSpec, InputStream, JsonOutputSpec, LineDecoration, LoggingSpec, OutputStreamSpec, PipeSpec, RotationSpec},
+    utils::{mask_secrets, strip_ansi},
 };
 
 pub struct Stdout {
@@ -18,6 +25,36 @@ pub struct Stderr {
 
 pub struct File {
     pub stream: Mutex<BufWriter<fs::File>>,
+    pub rotation: Option<Rotation>,
+}
+
+/// State of a file which is rotated once it exceeds its maximum size
+pub struct Rotation {
+    pub spec: RotationSpec,
+    pub path: PathBuf,
+    /// Size of the file including the buffered output
+    pub size: u64,
+    /// Background compression of the last rotated file
+    pub compression: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Rotation {
+    /// Waits for the background compression of the last rotated file and returns its error
+    fn finish_compression(&mut self) -> io::Result<()> {
+        match self.compression.take().map(|compression| compression.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("Compression of the rotated log file panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Rotation {
+    fn drop(&mut self) {
+        if let Some(compression) = self.compression.take() {
+            let _ = compression.join();
+        }
+    }
 }
 
 pub struct Writer {
@@ -26,11 +63,81 @@ pub struct Writer {
 
 pub struct Null;
 
+#[derive(Default)]
+struct LineBuffer {
+    line: Vec<u8>,
+    /// Time at which the first part of the line was written
+    started_at: Option<DateTime<Local>>,
+}
+
+/// Buffers the output of the stdout and stderr streams until their lines are complete,
+/// so that partial lines of different streams do not mix
+#[derive(Default)]
+pub struct LineBuffers {
+    stdout: LineBuffer,
+    stderr: LineBuffer,
+}
+
+impl LineBuffers {
+    /// Buffers the written output and returns the completed lines with the time they were started at
+    pub fn write(&mut self, stream: InputStream, buf: &[u8]) -> Vec<(Vec<u8>, DateTime<Local>)> {
+        let buffer = if stream.is_stderr() { &mut self.stderr } else { &mut self.stdout };
+        let mut lines = Vec::new();
+        for chunk in buf.split_inclusive(|c| *c == b'\n') {
+            let started_at = *buffer.started_at.get_or_insert_with(Local::now);
+            buffer.line.extend_from_slice(chunk);
+            if chunk.ends_with(b"\n") {
+                lines.push((std::mem::take(&mut buffer.line), started_at));
+                buffer.started_at = None;
+            }
+        }
+        lines
+    }
+
+    /// Returns the incomplete lines of both streams (completed with a newline)
+    pub fn take_partial(&mut self) -> Vec<(InputStream, Vec<u8>, DateTime<Local>)> {
+        let mut lines = Vec::new();
+        for (stream, buffer) in [(InputStream::Stdout, &mut self.stdout), (InputStream::Stderr, &mut self.stderr)] {
+            if let Some(started_at) = buffer.started_at.take() {
+                let mut line = std::mem::take(&mut buffer.line);
+                line.push(b'\n');
+                lines.push((stream, line, started_at));
+            }
+        }
+        lines
+    }
+}
+
+/// Writer decorating every line written to the inner stream (e.g. with a prefix or a timestamp,
+/// or by removing the ansi escape sequences).
+/// Lines are buffered until complete, so that lines written from different threads do not mix.
+pub struct Decorated {
+    pub decoration: LineDecoration,
+    pub buffers: LineBuffers,
+    pub stream: Box<OutputStream>,
+}
+
+/// Writer of structured events as json lines (one event per line).
+/// Output written to it is split into lines, each of which is written as a separate event.
+pub struct JsonLines {
+    pub file: fs::File,
+    /// Fields added to every event
+    pub fields: Map<String, Value>,
+    pub strip_ansi: bool,
+    /// Secret values masked within the events
+    pub secrets: Vec<String>,
+    pub buffers: LineBuffers,
+}
+
 impl std::io::Write for Stdout {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.stream.write(buf)
     }
 
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.stream.lock().write_all(buf)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.stream.flush()
     }
@@ -46,13 +153,58 @@ impl std::io::Write for Stderr {
     }
 }
 
+impl File {
+    /// Rotates the file if writing the given number of bytes would exceed its maximum size.
+    /// If the file has already been rotated by another writer, it is only reopened.
+    fn rotate(&mut self, len: usize) -> io::Result<()> {
+        let rotation = match &mut self.rotation {
+            Some(rotation) if rotation.size > 0 && rotation.size + len as u64 > rotation.spec.max_size => rotation,
+            _ => return Ok(()),
+        };
+        let mut stream = self.stream.lock().unwrap();
+        stream.flush()?;
+        let is_current = fs::metadata(&rotation.path)
+            .map(|metadata| metadata.ino() == stream.get_ref().metadata().map(|own| own.ino()).unwrap_or(0))
+            .unwrap_or(false);
+        if is_current {
+            // The previous rotated file has to be compressed before it is shifted
+            rotation.finish_compression()?;
+            rotation.compression = rotation.spec.rotate(&rotation.path)?;
+        }
+        let file = fs::OpenOptions::new().create(true).append(true).open(&rotation.path)?;
+        rotation.size = file.metadata()?.len();
+        *stream = BufWriter::new(file);
+        Ok(())
+    }
+}
+
 impl std::io::Write for File {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.stream.lock().unwrap().write(buf)
+        self.rotate(buf.len())?;
+        let written = self.stream.lock().unwrap().write(buf)?;
+        if let Some(rotation) = &mut self.rotation {
+            rotation.size += written as u64;
+        }
+        Ok(written)
+    }
+
+    /// Writes the buffer under a single lock, so that the buffered file is only ever flushed
+    /// at the boundaries of the written chunks (e.g. complete lines)
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.rotate(buf.len())?;
+        self.stream.lock().unwrap().write_all(buf)?;
+        if let Some(rotation) = &mut self.rotation {
+            rotation.size += buf.len() as u64;
+        }
+        Ok(())
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.stream.lock().unwrap().flush()
+        self.stream.lock().unwrap().flush()?;
+        match &mut self.rotation {
+            Some(rotation) => rotation.finish_compression(),
+            None => Ok(()),
+        }
     }
 }
 
@@ -66,6 +218,106 @@ impl std::io::Write for Writer {
     }
 }
 
+impl Decorated {
+    /// Writes the decorated line to the inner stream
+    fn write_line(&mut self, line: &[u8], started_at: DateTime<Local>) -> io::Result<()> {
+        let line = mask_secrets(line, &self.decoration.secrets);
+        let mut decorated = self.decoration.render(started_at).into_bytes();
+        if self.decoration.strip_ansi {
+            decorated.extend_from_slice(&strip_ansi(&line));
+        } else {
+            decorated.extend_from_slice(&line);
+        }
+        self.stream.write_all(&decorated)
+    }
+
+    /// Writes the output of the given stream, lines are buffered until complete
+    pub fn write_stream(&mut self, stream: InputStream, buf: &[u8]) -> io::Result<usize> {
+        for (line, started_at) in self.buffers.write(stream, buf) {
+            self.write_line(&line, started_at)?;
+        }
+        Ok(buf.len())
+    }
+}
+
+impl std::io::Write for Decorated {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_stream(InputStream::Stdout, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for (_, line, started_at) in self.buffers.take_partial() {
+            self.write_line(&line, started_at)?;
+        }
+        self.stream.flush()
+    }
+}
+
+impl JsonLines {
+    /// Writes an event (json object) tagged with the timestamp and the common fields
+    pub fn write_event(&mut self, event: &Value) -> io::Result<()> {
+        let mut record = Map::new();
+        record.insert("timestamp".to_string(), Local::now().to_rfc3339().into());
+        record.extend(self.fields.clone());
+        if let Value::Object(fields) = event {
+            record.extend(fields.clone());
+        }
+        let mut record = Value::Object(record);
+        mask_value(&mut record, &self.secrets);
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        // Events are written at once, so that events written from different threads do not mix
+        self.file.write_all(&line)
+    }
+
+    /// Writes a line of the command output as an event
+    fn write_line(&mut self, stream: InputStream, line: &[u8], started_at: DateTime<Local>) -> io::Result<()> {
+        let line = if self.strip_ansi { strip_ansi(line) } else { line.into() };
+        let line = String::from_utf8_lossy(&line);
+        self.write_event(&json!({
+            "timestamp": started_at.to_rfc3339(),
+            "event": "output",
+            "stream": if stream.is_stderr() { "stderr" } else { "stdout" },
+            "line": line.trim_end_matches(&['\n', '\r'][..]),
+        }))
+    }
+
+    /// Writes the command output, lines are buffered until complete
+    pub fn write_output(&mut self, stream: InputStream, buf: &[u8]) -> io::Result<usize> {
+        for (line, started_at) in self.buffers.write(stream, buf) {
+            self.write_line(stream, &line, started_at)?;
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Masks the secret values within all the strings of a json value
+fn mask_value(value: &mut Value, secrets: &[String]) {
+    match value {
+        Value::String(text) => {
+            if let std::borrow::Cow::Owned(masked) = mask_secrets(text.as_bytes(), secrets) {
+                *text = String::from_utf8_lossy(&masked).to_string();
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| mask_value(item, secrets)),
+        Value::Object(fields) => fields.values_mut().for_each(|item| mask_value(item, secrets)),
+        _ => {}
+    }
+}
+
+impl std::io::Write for JsonLines {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_output(InputStream::Stdout, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for (stream, line, started_at) in self.buffers.take_partial() {
+            self.write_line(stream, &line, started_at)?;
+        }
+        self.file.flush()
+    }
+}
+
 impl std::io::Write for Null {
     fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
         Ok(0)
@@ -83,6 +335,8 @@ pub enum OutputStream {
     File(File),
     Writer(Writer),
     Null(Null),
+    Decorated(Decorated),
+    Json(JsonLines),
 }
 
 impl OutputStream {
@@ -98,16 +352,23 @@ impl OutputStream {
         })
     }
 
-    pub fn new_file(path: impl AsRef<Path>, append: bool) -> Self {
-        let file = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .append(append)
-            .open(path)
-            .unwrap(); // TODO: handle error
-        OutputStream::File(File {
+    pub fn new_file(path: impl AsRef<Path>, append: bool) -> Result<Self> {
+        let file = open_file(path.as_ref(), fs::OpenOptions::new().write(true).append(append))?;
+        Ok(OutputStream::File(File {
             stream: Mutex::new(BufWriter::new(file)),
-        })
+            rotation: None,
+        }))
+    }
+
+    /// Creates a file output which is appended to and rotated once it exceeds its maximum size
+    pub fn new_rotating_file(path: impl AsRef<Path>, spec: RotationSpec) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = open_file(&path, fs::OpenOptions::new().append(true))?;
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        Ok(OutputStream::File(File {
+            stream: Mutex::new(BufWriter::new(file)),
+            rotation: Some(Rotation { spec, path, size, compression: None }),
+        }))
     }
 
     #[allow(dead_code)]
@@ -121,6 +382,34 @@ impl OutputStream {
     pub fn new_null() -> Self {
         OutputStream::Null(Null)
     }
+
+    pub fn new_json(spec: JsonOutputSpec) -> Result<Self> {
+        let file = open_file(&spec.file, fs::OpenOptions::new().append(true))?;
+        Ok(OutputStream::Json(JsonLines {
+            file,
+            fields: spec.fields,
+            strip_ansi: spec.strip_ansi,
+            secrets: spec.secrets,
+            buffers: LineBuffers::default(),
+        }))
+    }
+
+    pub fn new_decorated(stream: OutputStream, decoration: LineDecoration) -> Self {
+        OutputStream::Decorated(Decorated {
+            decoration,
+            buffers: LineBuffers::default(),
+            stream: Box::new(stream),
+        })
+    }
+
+    /// Writes the output of the given input stream
+    pub fn write_stream(&mut self, stream: InputStream, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputStream::Decorated(ref mut decorated) => decorated.write_stream(stream, buf),
+            OutputStream::Json(ref mut json) => json.write_output(stream, buf),
+            output => output.write(buf),
+        }
+    }
 }
 
 impl std::io::Write for OutputStream {
@@ -131,6 +420,20 @@ impl std::io::Write for OutputStream {
             OutputStream::File(ref mut file) => file.write(buf),
             OutputStream::Writer(ref mut writer) => writer.write(buf),
             OutputStream::Null(ref mut null) => null.write(buf),
+            OutputStream::Decorated(ref mut decorated) => decorated.write(buf),
+            OutputStream::Json(ref mut json) => json.write(buf),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            OutputStream::Stdout(ref mut stdout) => stdout.write_all(buf),
+            OutputStream::Stderr(ref mut stderr) => stderr.write_all(buf),
+            OutputStream::File(ref mut file) => file.write_all(buf),
+            OutputStream::Writer(ref mut writer) => writer.write_all(buf),
+            OutputStream::Null(_) => Ok(()),
+            OutputStream::Decorated(ref mut decorated) => decorated.write_all(buf),
+            OutputStream::Json(ref mut json) => json.write_all(buf),
         }
     }
 
@@ -141,76 +444,227 @@ impl std::io::Write for OutputStream {
             OutputStream::File(ref mut file) => file.flush(),
             OutputStream::Writer(ref mut writer) => writer.flush(),
             OutputStream::Null(ref mut null) => null.flush(),
+            OutputStream::Decorated(ref mut decorated) => decorated.flush(),
+            OutputStream::Json(ref mut json) => json.flush(),
         }
     }
 }
 
-impl From<OutputStreamSpec> for OutputStream {
-    fn from(spec: OutputStreamSpec) -> Self {
+/// Opens (and creates if missing) a log file
+fn open_file(path: &Path, options: &mut fs::OpenOptions) -> Result<fs::File> {
+    options.create(true)
+        .open(path)
+        .map_err(|e| format_err!("Cannot open log file '{}': {}", path.display(), e))
+}
+
+impl TryFrom<OutputStreamSpec> for OutputStream {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: OutputStreamSpec) -> Result<Self> {
         match spec {
-            OutputStreamSpec::Stdout => OutputStream::new_stdout(),
-            OutputStreamSpec::Stderr => OutputStream::new_stderr(),
+            OutputStreamSpec::Stdout => Ok(OutputStream::new_stdout()),
+            OutputStreamSpec::Stderr => Ok(OutputStream::new_stderr()),
+            OutputStreamSpec::File(FileOutputSpec { file, rotation: Some(rotation), .. }) => {
+                OutputStream::new_rotating_file(file, rotation)
+            },
             OutputStreamSpec::File(f) => OutputStream::new_file(
                 f.file, f.append,
-            )
+            ),
+            OutputStreamSpec::Json(j) => OutputStream::new_json(j),
         }
     }
 }
 
 pub trait MultiWriter {
     fn write_stream(&mut self, stream: InputStream, buf: &[u8]) -> io::Result<usize>;
+}
 
-    fn flush_stream(&mut self, stream: InputStream) -> io::Result<()>;
+/// Output of a log handler
+pub struct Pipe {
+    pub input: InputStream,
+    pub output: OutputStream,
+    /// Log level of the internal messages (defaults to the level of the logger)
+    pub level: Option<LogLevel>,
+    /// Whether the internal messages are written to the output
+    pub internal: bool,
 }
 
 #[derive(Default)]
 pub struct MultiOutputStream {
-    pub outputs: Vec<(InputStream, OutputStream)>,
+    pub pipes: Vec<Pipe>,
 }
 
 impl MultiOutputStream {
     pub fn new() -> Self {
-        MultiOutputStream { outputs: Vec::new() }
+        MultiOutputStream { pipes: Vec::new() }
     }
 
-    pub fn from_spec(specs: LoggingSpec) -> Self {
-        let mut outputs = Vec::new();
-
-        for PipeSpec { output, input } in specs.pipes {
-            outputs.push((input, output.into()));
+    /// Creates output streams from the spec.
+    /// If a prefix is given, the lines written to the console and the files are prefixed with it
+    /// (unless the handler has its own prefix). Prefixed outputs are line buffered, so that the lines
+    /// of parallel tasks logging to the same file do not mix.
+    pub fn from_spec(specs: LoggingSpec, prefix: Option<&str>) -> Result<Self> {
+        let mut pipes = Vec::new();
+
+        for PipeSpec { output, input, mut decoration, level, internal } in specs.pipes {
+            let is_json = matches!(output, OutputStreamSpec::Json(_));
+            if !is_json && decoration.prefix.is_none() {
+                decoration.prefix = prefix.map(String::from);
+            }
+            let output = OutputStream::try_from(output)?;
+            let stream = if decoration.is_empty() {
+                output
+            } else {
+                OutputStream::new_decorated(output, decoration)
+            };
+            pipes.push(Pipe { input, output: stream, level, internal });
         }
 
-        Self { outputs }
-    }
-}
-
-impl std::io::Write for MultiOutputStream {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        MultiWriter::write_stream(self, InputStream::Stdout, buf)
+        Ok(Self { pipes })
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        MultiWriter::flush_stream(self, InputStream::Stdout)
+    /// Flushes all the outputs
+    pub fn flush(&mut self) -> io::Result<()> {
+        for pipe in &mut self.pipes {
+            pipe.output.flush()?;
+        }
+        Ok(())
     }
 }
 
 impl MultiWriter for MultiOutputStream {
     fn write_stream(&mut self, stream: InputStream, buf: &[u8]) -> io::Result<usize> {
         let mut written = 0;
-        for (input, output) in &mut self.outputs {
-            if input.is_compatible(stream) {
-                written = output.write(buf)?.max(written);
+        for pipe in &mut self.pipes {
+            if pipe.input.is_compatible(stream) {
+                written = pipe.output.write_stream(stream, buf)?.max(written);
             }
         }
         Ok(written)
     }
+}
 
-    fn flush_stream(&mut self, stream: InputStream) -> io::Result<()> {
-        for (input, output) in &mut self.outputs {
-            if input.is_compatible(stream) {
-                output.flush()?;
-            }
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::PathBuf;
+    use chrono::{Duration, Local, TimeZone};
+    use crate::config::TimestampMode;
+    use crate::logging::{InputStream, JsonOutputSpec, LineBuffers, LineDecoration, OutputStream, RotationSpec, TimestampSpec};
+
+    #[test]
+    fn test_line_buffers() {
+        let mut buffers = LineBuffers::default();
+        assert!(buffers.write(InputStream::Stdout, b"hel").is_empty());
+        let lines = buffers.write(InputStream::Stderr, b"error\n");
+        assert_eq!(lines[0].0, b"error\n");
+        let lines = buffers.write(InputStream::Stdout, b"lo\nwor");
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].0, b"hello\n");
+
+        let partial = buffers.take_partial();
+        assert_eq!(partial.len(), 1);
+        assert_eq!(partial[0].1, b"wor\n");
+        assert!(buffers.take_partial().is_empty());
+    }
+
+    #[test]
+    fn test_decoration() {
+        let started_at = Local.ymd(2022, 1, 31).and_hms(12, 30, 0);
+        let mut decoration = LineDecoration {
+            timestamps: Some(TimestampSpec {
+                mode: TimestampMode::Elapsed,
+                format: TimestampMode::Elapsed.default_format().to_string(),
+                started_at,
+            }),
+            prefix: Some("[build] ".to_string()),
+            strip_ansi: false,
+            secrets: Vec::new(),
+        };
+        assert_eq!(decoration.render(started_at + Duration::milliseconds(61500)), "00:01:01.500 [build] ");
+
+        decoration.timestamps = decoration.timestamps.map(|timestamps| TimestampSpec {
+            mode: TimestampMode::Wall,
+            format: "%H:%M".to_string(),
+            ..timestamps
+        });
+        assert_eq!(decoration.render(started_at), "12:30 [build] ");
+    }
+
+    #[test]
+    fn test_decorated_output() {
+        let path = std::env::temp_dir().join(format!("nauman-decorated-{}.log", std::process::id()));
+        let decoration = LineDecoration { timestamps: None, prefix: Some("[build] ".to_string()), strip_ansi: true, secrets: Vec::new() };
+        let mut output = OutputStream::new_decorated(OutputStream::new_file(&path, false).unwrap(), decoration);
+        output.write_stream(InputStream::Stdout, b"first \x1b[3").unwrap();
+        output.write_stream(InputStream::Stdout, b"2m").unwrap();
+        output.write_stream(InputStream::Stderr, b"\x1b[31merror\x1b[0m\n").unwrap();
+        output.write_stream(InputStream::Stdout, b"line\nsecond").unwrap();
+        output.flush().unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "[build] error\n[build] first line\n[build] second\n");
+    }
+
+    #[test]
+    fn test_masked_output() {
+        let path = std::env::temp_dir().join(format!("nauman-masked-{}.log", std::process::id()));
+        let decoration = LineDecoration { secrets: vec!["hunter2".to_string()], ..LineDecoration::default() };
+        let mut output = OutputStream::new_decorated(OutputStream::new_file(&path, false).unwrap(), decoration);
+        output.write_stream(InputStream::Stdout, b"password: hun").unwrap();
+        output.write_stream(InputStream::Stdout, b"ter2\nhunter").unwrap();
+        output.write_stream(InputStream::Stdout, b"2").unwrap();
+        output.flush().unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "password: ***\n***\n");
+    }
+
+    #[test]
+    fn test_rotating_file() {
+        let dir = std::env::temp_dir().join(format!("nauman-rotating-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("full.log");
+        let spec = RotationSpec { max_size: 12, max_files: 2, compress: false };
+        let mut output = OutputStream::new_rotating_file(&path, spec).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            output.write_stream(InputStream::Stdout, line.as_bytes()).unwrap();
         }
-        Ok(())
+        output.flush().unwrap();
+
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap();
+        let (current, first, second) = (read(path.clone()), read(spec.rotated_path(&path, 1)), read(spec.rotated_path(&path, 2)));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(current, "fourth\n");
+        assert_eq!(first, "third\n");
+        assert_eq!(second, "second\n");
+    }
+
+    #[test]
+    fn test_compression_error() {
+        let dir = std::env::temp_dir().join(format!("nauman-compression-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let spec = RotationSpec { max_size: 12, max_files: 2, compress: true };
+        let mut output = OutputStream::new_rotating_file(dir.join("full.log"), spec).unwrap();
+        if let OutputStream::File(file) = &mut output {
+            file.rotation.as_mut().unwrap().compression = Some(std::thread::spawn(|| {
+                Err(std::io::Error::other("Failed to compress rotated log file"))
+            }));
+        }
+        let error = output.flush().err();
+        let second = output.flush();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(error.unwrap().to_string(), "Failed to compress rotated log file");
+        assert!(second.is_ok());
+    }
+
+    #[test]
+    fn test_open_error() {
+        let file = std::env::temp_dir().join(format!("nauman-missing-{}", std::process::id())).join("events.jsonl");
+        let spec = JsonOutputSpec { file: file.clone(), fields: Default::default(), strip_ansi: false, secrets: Vec::new() };
+        let error = OutputStream::new_json(spec).err().unwrap();
+        assert!(error.to_string().starts_with(&format!("Cannot open log file '{}'", file.display())));
     }
 }
@@ -1,31 +1,47 @@
-#[doc = include_str!("../README.md")]
-
-#[macro_use] extern crate prettytable;
+#![doc = include_str!("../README.md")]
 
 #[cfg(test)]
 extern crate test_case;
 
 use std::{
     fs,
+    path::{Path, PathBuf},
 };
 use std::error::Error;
-use std::path::PathBuf;
+use std::io::IsTerminal;
 use crate::{
-    execution::{Executor},
+    common::Env,
+    execution::{ExecutionContext, Executor, RunInfo},
     logging::Logger
 };
-use clap::{Parser, ValueHint};
+use chrono::Local;
+use clap::{AppSettings, Parser, Subcommand, ValueHint};
 use crate::common::LogLevel;
 use anyhow::{anyhow, Context as AnyhowContext, Result};
-use crate::config::{LogHandler, LogHandlerType};
+use crate::config::{ExitCodePolicy, LogHandler, LogHandlerType, Summary};
+use crate::flow::TaskSelection;
+use crate::history::{History, RunRecord, runs_table};
 use crate::logging::pprint;
+use crate::state::{RunArgs, RunState};
+use crate::utils::{content_hash, resolve_cwd};
+use crate::validate::validate_job;
 
 mod common;
 mod config;
 mod logging;
 mod flow;
+mod history;
+mod http;
 mod execution;
+mod expression;
+mod lock;
+mod retention;
+mod script;
+mod state;
+mod summary;
+mod template;
 mod utils;
+mod validate;
 
 /// Parse a single key-value pair
 fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
@@ -41,11 +57,13 @@ fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync +
     Ok((s[..pos].parse()?, s[pos + 1..].parse()?))
 }
 
-#[derive(Parser)]
+#[derive(Parser, Default)]
 #[clap(version = "1.0", author = "Egor D. <egordmitriev2@gmail.com>")]
+#[clap(setting = AppSettings::SubcommandsNegateReqs, setting = AppSettings::ArgsNegateSubcommands)]
 struct Opts {
     /// Path to job yaml file
-    job: String,
+    #[clap(required = true)]
+    job: Option<String>,
     /// A level of verbosity, and can be used multiple times (default: info)
     #[clap(short, long, arg_enum)]
     level: Option<LogLevel>,
@@ -61,28 +79,113 @@ struct Opts {
     /// Whether to use system environment variables (default: true)
     #[clap(long)]
     system_env: Option<bool>,
+    /// Maximum number of tasks to execute in parallel (default: 1)
+    #[clap(short = 'j', long)]
+    max_parallel: Option<usize>,
+    /// Exit code policy: first_failure, last, always_zero or a fixed number (default: first_failure)
+    #[clap(long)]
+    exit_code: Option<ExitCodePolicy>,
+    /// Whether failing hooks should fail the job (default: false)
+    #[clap(long)]
+    fail_on_hook_failure: Option<bool>,
+    /// Only execute the tasks matching the given ids or glob patterns
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    only: Vec<String>,
+    /// Skip the tasks matching the given ids or glob patterns
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    skip: Vec<String>,
+    /// Start from the first task matching the given id or glob pattern
+    #[clap(long)]
+    from: Option<String>,
+    /// Stop after the last task matching the given id or glob pattern
+    #[clap(long)]
+    until: Option<String>,
+    /// Do not execute any hooks
+    #[clap(long)]
+    no_hooks: bool,
+    /// Write the run summary as json, junit or markdown (to the log dir unless FILE is given)
+    #[clap(long, multiple_occurrences(true), number_of_values = 1, value_name = "FORMAT[=FILE]")]
+    summary: Vec<Summary>,
+    /// Label describing what has started the run, stored in the run history
+    /// (default: manual when run from a terminal, automated otherwise)
+    #[clap(long)]
+    trigger: Option<String>,
     /// List of env variable overrides
     #[clap(short = 'e', parse(try_from_str = parse_key_val), multiple_occurrences(true), number_of_values = 1)]
     env: Vec<(String, String)>,
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-impl Default for Opts {
-    fn default() -> Self {
-        Self {
-            job: "".to_string(),
-            level: None,
-            dry_run: false,
-            ansi: None,
-            log_dir: None,
-            system_env: None,
-            env: vec![]
-        }
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Check a job file for problems without executing it
+    Validate {
+        /// Path to job yaml file
+        job: String,
+    },
+    /// Print the JSON schema of the job file format
+    Schema,
+    /// Resume a failed run from its log directory, skipping the tasks which have completed
+    Resume {
+        /// Path to the log directory of the run
+        run_dir: String,
+        /// Resume even if the job file has changed since the run
+        #[clap(long)]
+        force: bool,
+    },
+    /// Manage the log directories of the previous runs
+    Logs {
+        #[clap(subcommand)]
+        command: LogsCommand,
+    },
+    /// List the recent runs stored in the run history
+    History {
+        /// Only list the runs of the job with the given id
+        job: Option<String>,
+        /// Only list the runs of the given job file (stored in its log dir unless --log-dir is given)
+        #[clap(long = "job", value_hint = ValueHint::FilePath)]
+        job_file: Option<String>,
+        /// Directory the logs are stored in (default: current directory)
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        log_dir: Option<String>,
+        /// Maximum number of runs to list
+        #[clap(short = 'n', long, default_value = "20")]
+        limit: usize,
+        #[clap(subcommand)]
+        command: Option<HistoryCommand>,
+    },
+}
+
+#[derive(Subcommand)]
+enum LogsCommand {
+    /// Delete or compress the previous runs of a job according to its retention policy
+    Prune {
+        /// Path to job yaml file
+        job: String,
+        /// Directory the logs are stored in (default: log dir of the job)
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        log_dir: Option<String>,
+        /// Only list the runs which would be deleted or compressed
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// Show the stored summary of a run and the paths to its logs
+    Show {
+        /// Id of the run
+        run: String,
+    },
 }
 
 fn main() {
    match run() {
-       Ok(_) => {}
+       Ok(exit_code) => {
+           std::process::exit(exit_code);
+       }
        Err(e) => {
            eprintln!("{}", pprint::error(&format!("{}", e)));
            std::process::exit(1);
@@ -90,25 +193,179 @@ fn main() {
    }
 }
 
-fn run() -> Result<()> {
+fn run() -> Result<i32> {
     let opts: Opts = Opts::parse();
-    process(opts)
+    match opts.command {
+        Some(Command::Validate { job }) => validate(&job),
+        Some(Command::Schema) => {
+            println!("{}", schema_json()?);
+            Ok(0)
+        }
+        Some(Command::Resume { run_dir, force }) => resume(&run_dir, force),
+        Some(Command::Logs { command: LogsCommand::Prune { job, log_dir, dry_run } }) => prune(&job, log_dir, dry_run),
+        Some(Command::History { job, job_file, log_dir, limit, command }) => {
+            let (job, log_root) = history_location(job, job_file.as_deref(), log_dir)?;
+            let history = History::in_dir(&log_root);
+            match command {
+                Some(HistoryCommand::Show { run }) => history_show(&history, &run),
+                None => history_list(&history, job.as_deref(), limit),
+            }
+        }
+        None => process(opts),
+    }
 }
 
-fn process(opts: Opts) -> Result<()> {
-    // Read and parse the job file
-    let contents = fs::read_to_string(&opts.job)
-        .with_context(|| format!("Failed to read job file: {}", &opts.job))?;
-    let mut job: config::Job = serde_yaml::from_str(&contents)
-        .map_err(|e| anyhow!("Failed to parse job file: Error {}", e))?;
-
-    // Update job
-    let filename = PathBuf::from(&opts.job).file_stem().and_then(|f| f.to_str()).unwrap().to_string();
-    job.id = Some(job.id.unwrap_or(filename));
-
-    // Merge options
-    // TODO: move this to a separate function
-    let mut options = job.options.clone().unwrap_or_default();
+/// Returns the JSON schema of the job file format
+fn schema_json() -> Result<String> {
+    serde_json::to_string_pretty(&config::Job::schema())
+        .map_err(|e| anyhow!("Failed to serialize job schema: {}", e))
+}
+
+/// Validates the job file, prints all the problems found and returns the exit code of the process
+fn validate(job: &str) -> Result<i32> {
+    let contents = fs::read_to_string(job)
+        .with_context(|| format!("Failed to read job file: {}", job))?;
+
+    let problems = validate_job(&contents, job);
+    for problem in &problems {
+        let message = format!("{}:{}", job, problem);
+        eprintln!("{}", if problem.is_error() { pprint::error(&message) } else { pprint::warning(&message) });
+    }
+    let errors = problems.iter().filter(|problem| problem.is_error()).count();
+    if errors == 0 {
+        println!("{}", pprint::success(&format!("{}: Job file is valid", job)));
+        Ok(0)
+    } else {
+        eprintln!("{}", pprint::error(&format!("{}: Found {} error(s)", job, errors)));
+        Ok(1)
+    }
+}
+
+/// Returns the id of the job whose runs are listed and the log directory the history is stored in.
+/// The history of a job file is stored in the log dir of the job, unless another one is given.
+fn history_location(job_id: Option<String>, job_file: Option<&str>, log_dir: Option<String>) -> Result<(Option<String>, PathBuf)> {
+    let job = job_file.map(config::Job::read).transpose()?.map(|(job, _)| job);
+    let log_root = match (log_dir, &job) {
+        (Some(log_dir), _) => PathBuf::from(log_dir),
+        (None, Some(job)) => job_log_root(job)?,
+        (None, None) => PathBuf::new(),
+    };
+    Ok((job_id.or_else(|| job.and_then(|job| job.id)), log_root))
+}
+
+/// Resolves the directory the runs of the job are logged in, the same way as it is done for the runs
+fn job_log_root(job: &config::Job) -> Result<PathBuf> {
+    let job_id = job.id.clone().expect("Job id not set");
+    let options = job.options.clone().unwrap_or_default();
+    let mut context = ExecutionContext::new(options, RunInfo::new(&job_id, &job.name), std::env::current_dir()?);
+    context.env = Env::from_system();
+    let cwd = job.cwd.as_ref().map(|cwd| template::render(cwd, &context)).transpose()?;
+    let cwd = resolve_cwd(&context.cwd, cwd.as_ref());
+    Ok(resolve_cwd(&cwd, context.options.log_dir.as_ref()))
+}
+
+/// Lists the most recent runs (of the given job) stored in the history
+fn history_list(history: &History, job: Option<&str>, limit: usize) -> Result<i32> {
+    let records: Vec<RunRecord> = history.load()?.into_iter()
+        .filter(|record| job.map(|job| record.job_id == job).unwrap_or(true))
+        .collect();
+    if records.is_empty() {
+        println!("No runs found in history file: {}", history.path.display());
+        return Ok(0);
+    }
+    runs_table(&records[records.len().saturating_sub(limit)..]).printstd();
+    Ok(0)
+}
+
+/// Shows the stored summary of a run and the paths to its logs
+fn history_show(history: &History, run: &str) -> Result<i32> {
+    let record = history.find(run)?;
+    println!("Run:      {}", record.run_id);
+    println!("Job:      {} ({})", record.job_name, record.job_id);
+    println!("Status:   {:?} (exit code {})", record.status, record.exit_code);
+    println!("Started:  {}", record.started_at.format("%Y-%m-%d %H:%M:%S"));
+    println!("Finished: {} ({}s)", record.finished_at.format("%Y-%m-%d %H:%M:%S"), record.duration().num_seconds());
+    println!("Host:     {}", record.host.as_deref().unwrap_or("-"));
+    println!("Trigger:  {}", record.trigger);
+    record.tasks_table().printstd();
+    let log_files = record.log_files();
+    if !log_files.is_empty() {
+        println!("Logs:");
+        for file in log_files {
+            println!("  {}", file.display());
+        }
+    }
+    Ok(0)
+}
+
+/// Resumes the run stored in the given log directory and returns the exit code of the process
+fn resume(run_dir: &str, force: bool) -> Result<i32> {
+    let state = RunState::load(Path::new(run_dir))?;
+    let job_path = state.run.job_path.clone()
+        .ok_or_else(|| anyhow!("Run state does not contain the job file path"))?;
+    let contents = fs::read_to_string(&job_path)
+        .with_context(|| format!("Failed to read job file: {}", job_path.display()))?;
+
+    // Resuming with a modified job file may restore the results of different tasks
+    if state.run.job_hash.as_deref() != Some(content_hash(&contents).as_str()) {
+        let message = format!("Job file {} has changed since the run was started", job_path.display());
+        if !force {
+            return Err(anyhow!("{}. Use --force to resume anyway", message));
+        }
+        eprintln!("{}", pprint::warning(&message));
+    }
+
+    // The run continues with the env overrides and the task selection it was started with,
+    // its options are restored from the state
+    let args = state.run.args.clone();
+    let opts = Opts {
+        job: Some(job_path.to_string_lossy().to_string()),
+        trigger: Some("resume".to_string()),
+        env: args.env.into_iter().collect(),
+        only: args.selection.only,
+        skip: args.selection.skip,
+        from: args.selection.from,
+        until: args.selection.until,
+        no_hooks: args.no_hooks,
+        ..Opts::default()
+    };
+    execute(opts, Some((state, PathBuf::from(run_dir))))
+}
+
+/// Deletes or compresses the previous runs of the job according to its retention policy
+fn prune(job_path: &str, log_dir: Option<String>, dry_run: bool) -> Result<i32> {
+    let (job, _) = config::Job::read(job_path)?;
+    let job_id = job.id.clone().expect("Job id not set");
+    let options = job.options.clone().unwrap_or_default();
+    let policy = options.retention.clone()
+        .ok_or_else(|| anyhow!("Job \"{}\" has no retention policy", job.name))?;
+
+    let log_root = match log_dir {
+        Some(log_dir) => PathBuf::from(log_dir),
+        None => job_log_root(&job)?,
+    };
+
+    let runs = retention::find_runs(&log_root, &job_id)?;
+    let actions = retention::plan(&policy, &runs, Local::now().naive_local(), None);
+    if actions.is_empty() {
+        println!("No runs of job \"{}\" to prune in {}", job.name, log_root.display());
+    }
+    for (run, action) in actions {
+        println!("{}", pprint::run_pruned(&run.path, action, dry_run));
+        if !dry_run {
+            retention::apply(run, action)?;
+        }
+    }
+    Ok(0)
+}
+
+/// Executes the job and returns the exit code of the process
+fn process(opts: Opts) -> Result<i32> {
+    execute(opts, None)
+}
+
+/// Merges the command line options into the job options
+fn merge_options(mut options: config::Options, opts: &Opts) -> Result<config::Options> {
     if let Some(level) = opts.level {
         options.log_level = level;
     }
@@ -118,23 +375,53 @@ fn process(opts: Opts) -> Result<()> {
     if let Some(ansi) = opts.ansi {
         options.ansi = ansi;
     }
-    if let Some(log_dir) = opts.log_dir {
-        options.log_dir = Some(log_dir);
+    if let Some(log_dir) = &opts.log_dir {
+        options.log_dir = Some(log_dir.clone());
     }
     if let Some(system_env) = opts.system_env {
         options.system_env = system_env;
     }
-    if let Some(env) = job.env.as_mut() {
-        env.extend(opts.env);
-    } else {
-        job.env = Some(opts.env.iter().cloned().collect());
+    if let Some(max_parallel) = opts.max_parallel {
+        options.max_parallel = max_parallel;
+    }
+    if let Some(exit_code) = opts.exit_code {
+        options.exit_code = exit_code;
+    }
+    if let Some(fail_on_hook_failure) = opts.fail_on_hook_failure {
+        options.fail_on_hook_failure = fail_on_hook_failure;
+    }
+    for summary in &opts.summary {
+        // Summary files given on the command line are relative to the working directory
+        let output = summary.output.as_ref().map(|output| std::env::current_dir().map(|cwd| cwd.join(output)))
+            .transpose()?
+            .map(|output| output.to_string_lossy().to_string());
+        options.summary.push(Summary { output, ..summary.clone() });
+    }
+    Ok(options)
+}
+
+/// Executes the job (optionally resuming a run stored in the given directory)
+/// and returns the exit code of the process
+fn execute(opts: Opts, resumed: Option<(RunState, PathBuf)>) -> Result<i32> {
+    // Read and parse the job file
+    let job_path = opts.job.as_deref().ok_or_else(|| anyhow!("No job file given"))?;
+    let (mut job, contents) = config::Job::read(job_path)?;
+
+    // A resumed run continues with the options it was started with
+    let options = match resumed.as_ref().and_then(|(state, _)| state.options.clone()) {
+        Some(options) => options,
+        None => merge_options(job.options.clone().unwrap_or_default(), &opts)?,
     };
+    let overrides: Env = opts.env.into_iter().collect();
+    job.env.get_or_insert_with(Env::default).extend(overrides.clone());
+
+    // Parse the job to a flow
+    let mut flow = flow::Flow::parse(&job)
+        .map_err(|e| anyhow!("Failed parsing job: {}", e))?;
 
-    // Setup the logger
+    // Setup the logger (with the log handlers of the included files)
     colored::control::set_override(options.ansi);
-    let mut logging_handlers = if let Some(handlers) = job.logging.clone() {
-        handlers
-    } else { vec![]  };
+    let mut logging_handlers = flow.logging.clone();
     // Add console handler if none present
     if logging_handlers.iter().all(|h| h.handler != LogHandlerType::Console) {
         logging_handlers.push(LogHandler::default_console());
@@ -142,33 +429,56 @@ fn process(opts: Opts) -> Result<()> {
     // Build the logger
     let mut logger = Logger::new(logging_handlers, options.log_level);
 
-    // Parse the job to a flow
-    let flow = flow::Flow::parse(&job)
-        .map_err(|e| anyhow!("Failed parsing job: {}", e))?;
+    // Select the tasks to execute
+    let selection = TaskSelection { only: opts.only, skip: opts.skip, from: opts.from, until: opts.until };
+    flow.select(&selection)
+        .map_err(|e| anyhow!("Failed to select tasks: {}", e))?;
+    if opts.no_hooks {
+        flow.strip_hooks();
+    }
 
     // Create an executor for the given flow
-    let mut executor = Executor::new(options, &flow)
-        .map_err(|e| anyhow!("Failed to create executor: {}", e))?;
+    let mut executor = match resumed {
+        Some((state, run_dir)) => Executor::resume(options, &flow, state, run_dir),
+        None => Executor::new(options, &flow),
+    }.map_err(|e| anyhow!("Failed to create executor: {}", e))?;
+    executor.context.run.job_path = Some(fs::canonicalize(job_path)?);
+    executor.context.run.job_hash = Some(content_hash(&contents));
+    executor.context.run.trigger = opts.trigger.unwrap_or_else(|| {
+        if std::io::stdin().is_terminal() { "manual" } else { "automated" }.to_string()
+    });
+    executor.context.run.args = RunArgs { env: overrides, selection, no_hooks: opts.no_hooks };
 
     // Execute the flow
-    executor.execute(&mut logger)
+    let result = executor.execute(&mut logger)
         .map_err(|e| anyhow!("Fatal error occurred during job execution: {}", e))?;
 
-    Ok(())
+    Ok(result.exit_code)
 }
 
 #[cfg(test)]
 mod tests {
     use test_case::test_case;
     use std::path::PathBuf;
-    use crate::{Opts, process};
+    use crate::{Opts, history_location, process, resume, schema_json, validate};
+    use crate::history::History;
+    use crate::config::ExitCodePolicy;
 
+    #[test_case("conditions.yml")]
     #[test_case("env-vars.yml")]
     #[test_case("health-checks.yml")]
     #[test_case("hello-world.yml")]
+    #[test_case("includes.yml")]
+    #[test_case("locking.yml")]
     #[test_case("logging.yml")]
     #[test_case("multi-shell.yml")]
     #[test_case("outputs.yml")]
+    #[test_case("parallel.yml")]
+    #[test_case("retries.yml")]
+    #[test_case("scripts.yml")]
+    #[test_case("sub-jobs.yml")]
+    #[test_case("templating.yml")]
+    #[test_case("timeouts.yml")]
     fn integration_tests(example: &str) {
         let mut opts = Opts::default();
 
@@ -176,7 +486,742 @@ mod tests {
             .join("examples")
             .join(example).to_str().unwrap().to_string();
 
-        opts.job = example_path;
+        opts.job = Some(example_path);
         process(opts).expect("Failed to execute example job");
     }
+
+    #[test_case("hello-world.yml", None, 0)]
+    #[test_case("retries.yml", None, 2)]
+    #[test_case("retries.yml", Some(ExitCodePolicy::Last), 2)]
+    #[test_case("retries.yml", Some(ExitCodePolicy::AlwaysZero), 0)]
+    #[test_case("retries.yml", Some(ExitCodePolicy::Fixed(42)), 42)]
+    #[test_case("conditions.yml", Some(ExitCodePolicy::Last), 0)]
+    #[test_case("timeouts.yml", None, 1)]
+    fn exit_code_tests(example: &str, exit_code: Option<ExitCodePolicy>, expected: i32) {
+        let mut opts = Opts::default();
+
+        let example_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("examples")
+            .join(example).to_str().unwrap().to_string();
+
+        opts.job = Some(example_path);
+        opts.exit_code = exit_code;
+        assert_eq!(process(opts).expect("Failed to execute example job"), expected);
+    }
+
+    #[test_case(&[], &["*_do-not-retry-*"], false, 0 ; "skip failing task")]
+    #[test_case(&["*_do-not-retry-*"], &[], false, 2 ; "only failing task")]
+    #[test_case(&["*_do-not-retry-*"], &[], true, 2 ; "only failing task without hooks")]
+    fn selection_tests(only: &[&str], skip: &[&str], no_hooks: bool, expected: i32) {
+        let mut opts = Opts::default();
+
+        let example_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("examples")
+            .join("retries.yml").to_str().unwrap().to_string();
+
+        opts.job = Some(example_path);
+        opts.only = only.iter().map(|pattern| pattern.to_string()).collect();
+        opts.skip = skip.iter().map(|pattern| pattern.to_string()).collect();
+        opts.no_hooks = no_hooks;
+        assert_eq!(process(opts).expect("Failed to execute example job"), expected);
+    }
+
+    #[test]
+    fn resume_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-resume-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Resume
+cwd: {}
+tasks:
+  - id: download
+    run: echo download >> downloads.txt && echo "FILE=data.txt" >> $NAUMAN_OUTPUT_FILE
+  - id: process
+    run: test -f fixed.txt && test "$FILE" = data.txt && test "$TARGET" = prod
+  - id: notify
+    run: touch notified.txt
+"#, dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            skip: vec!["notify".to_string()],
+            env: vec![("TARGET".to_string(), "prod".to_string())],
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 1);
+
+        // Fix the failing task and resume the run with the same options
+        std::fs::write(dir.join("fixed.txt"), "").unwrap();
+        let run_dir = std::fs::read_dir(dir.join("logs")).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.is_dir())
+            .unwrap();
+        assert_eq!(resume(run_dir.to_str().unwrap(), false).expect("Failed to resume job"), 0);
+        assert_eq!(std::fs::read_to_string(dir.join("downloads.txt")).unwrap(), "download\n");
+        assert!(!dir.join("notified.txt").exists());
+        let history = std::fs::read_to_string(dir.join("logs").join("history.jsonl")).unwrap();
+        assert_eq!(history.lines().count(), 2);
+
+        // Resuming a modified job is refused unless forced
+        std::fs::write(&job_path, "name: Resume\ntasks:\n  - run: echo changed\n").unwrap();
+        assert!(resume(run_dir.to_str().unwrap(), false).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn history_location_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-history-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: History
+cwd: {}
+tasks:
+  - run: "true"
+options:
+  log_dir: runs
+"#, dir.display())).unwrap();
+
+        let opts = Opts { job: Some(job_path.to_str().unwrap().to_string()), ..Opts::default() };
+        assert_eq!(process(opts).expect("Failed to execute job"), 0);
+
+        let (job_id, log_root) = history_location(None, job_path.to_str(), None).unwrap();
+        let records = History::in_dir(&log_root).load().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(job_id.as_deref(), Some("job"));
+        assert_eq!(log_root, dir.join("runs"));
+        assert_eq!(records.len(), 1);
+    }
+
+    #[test]
+    fn job_timeout_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-job-timeout-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Deadline
+policy: always
+timeout: 1s
+cwd: {}
+tasks:
+  - run: sleep 30
+    timeout: 1m
+  - run: touch started.txt
+hooks:
+  after_job:
+    - run: touch reported.txt
+"#, dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        let now = std::time::Instant::now();
+        assert_eq!(process(opts).expect("Failed to execute job"), 1);
+        assert!(now.elapsed() < std::time::Duration::from_secs(20));
+        assert!(!dir.join("started.txt").exists());
+        assert!(dir.join("reported.txt").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_logging_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-json-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Events
+policy: always
+cwd: {}
+tasks:
+  - id: greet
+    run: echo hello && echo oops >&2 && exit 3
+    hooks:
+      on_failure:
+        - id: report
+          run: echo reported
+logging:
+  - type: json
+    output: {}
+"#, dir.display(), dir.join("events.jsonl").display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 3);
+
+        let events: Vec<serde_json::Value> = std::fs::read_to_string(dir.join("events.jsonl")).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Invalid event"))
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<&str> = events.iter().map(|event| event["event"].as_str().unwrap()).collect();
+        assert_eq!(names, vec![
+            "job_start", "task_start", "output", "output", "task_end", "hook_start", "output", "hook_end", "job_end"
+        ]);
+        assert!(events.iter().all(|event| event["run_id"].is_string() && event["timestamp"].is_string()));
+        // Stdout and stderr are captured concurrently, so their order is not fixed
+        let stderr = events.iter().find(|event| event["stream"] == "stderr").unwrap();
+        assert_eq!(stderr["line"], "oops");
+        assert_eq!(stderr["task_id"], "greet");
+        assert_eq!(events[4]["exit_code"], 3);
+        assert_eq!(events[4]["status"], "failure");
+        assert_eq!(events[6]["task_id"], "report");
+        assert_eq!(events[6]["focus_id"], "greet");
+        assert_eq!(events[8]["status"], "failed");
+        assert!(events[8].get("task_id").is_none());
+    }
+
+    #[test]
+    fn json_lifecycle_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-lifecycle-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("sub.yml"), r#"
+name: Sub
+tasks:
+  - id: inner
+    run: echo inner
+"#).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Lifecycle
+cwd: {dir}
+tasks:
+  - id: outer
+    job: {dir}/sub.yml
+logging:
+  - type: json
+    output: {dir}/events.jsonl
+    level: error
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 0);
+
+        let events: Vec<serde_json::Value> = std::fs::read_to_string(dir.join("events.jsonl")).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Invalid event"))
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        // The task start events are filtered out by the level, the job lifecycle events are not
+        assert!(events.iter().all(|event| event["event"] != "task_start"));
+        let names: Vec<(&str, &str)> = events.iter()
+            .filter(|event| event["event"].as_str().unwrap().starts_with("job_"))
+            .map(|event| (event["event"].as_str().unwrap(), event["job_name"].as_str().unwrap()))
+            .collect();
+        assert_eq!(names, vec![
+            ("job_start", "Lifecycle"), ("job_start", "Sub"), ("job_end", "Sub"), ("job_end", "Lifecycle")
+        ]);
+    }
+
+    #[test]
+    fn handler_levels_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-levels-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Levels
+cwd: {dir}
+tasks:
+  - id: greet
+    name: Greet
+    run: echo hello
+logging:
+  - type: file
+    output: {dir}/raw.log
+    internal: false
+  - type: file
+    output: {dir}/error.log
+    level: error
+  - type: file
+    output: {dir}/debug.log
+    level: debug
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 0);
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        let (raw, error, debug) = (read("raw.log"), read("error.log"), read("debug.log"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(raw, "hello\n");
+        assert_eq!(error, "hello\n");
+        assert!(debug.contains("Task: Greet"));
+        assert!(debug.contains("Task \"Greet\" completed with a zero exit status"));
+    }
+
+    #[test]
+    fn parallel_logging_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-parallel-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Parallel logging
+cwd: {dir}
+tasks:
+  - id: first
+    run: for i in $(seq 1 500); do printf 'first %s ' $i; printf 'done\n'; done
+  - id: second
+    run: for i in $(seq 1 500); do printf 'second %s ' $i; printf 'done\n'; done
+logging:
+  - type: file
+    output: {dir}/raw.log
+    internal: false
+options:
+  max_parallel: 2
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 0);
+
+        let raw = std::fs::read_to_string(dir.join("raw.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(raw.lines().count(), 1000);
+        for line in raw.lines() {
+            let (prefix, line) = line.split_once(' ').unwrap();
+            let task_id = prefix.trim_start_matches('[').trim_end_matches(']');
+            assert!(line.starts_with(task_id) && line.ends_with(" done"), "Mixed line: {}", line);
+        }
+    }
+
+    #[test]
+    fn secrets_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-secrets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("secrets.env"), "DB_PASSWORD=pa55word\n").unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Secrets
+cwd: {dir}
+env:
+  API_TOKEN: s3cr3t-token
+secrets:
+  env: [API_TOKEN]
+  file: secrets.env
+tasks:
+  - id: token
+    run: echo s3cr3t-token | tr -d '\n'
+  - id: password
+    run: printf 'password=%s\n' "$DB_PASSWORD"
+logging:
+  - type: file
+    output: {dir}/debug.log
+    level: debug
+  - type: json
+    output: {dir}/events.jsonl
+options:
+  summary:
+    - format: markdown
+      output: {dir}/summary.md
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 0);
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        let (debug, events, summary) = (read("debug.log"), read("events.jsonl"), read("summary.md"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        for contents in [&debug, &events, &summary] {
+            assert!(!contents.contains("s3cr3t-token") && !contents.contains("pa55word"));
+        }
+        assert!(debug.contains("$ echo *** | tr -d '\\n'"));
+        assert!(debug.contains("API_TOKEN=***"));
+        assert!(debug.contains("password=***"));
+        assert!(events.contains("\"line\":\"***\""));
+        assert!(summary.contains("echo ***"));
+    }
+
+    #[test]
+    fn secrets_invalid_line_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-secrets-invalid-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("secrets.env"), "DB_USER=admin\nDB_PASSWORD pa55word\n").unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Secrets
+cwd: {dir}
+secrets:
+  file: secrets.env
+tasks:
+  - run: echo "$DB_USER"
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        let error = process(opts).expect_err("Invalid secrets file accepted").to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(error.contains("Failed to load secrets file"), "{}", error);
+        assert!(error.contains("Invalid line 2"), "{}", error);
+        assert!(!error.contains("pa55word"));
+    }
+
+    #[test]
+    fn http_task_test() {
+        // Stand-in server answering the requests with the given responses in order
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in ["201 Created", "503 Service Unavailable"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buffer = [0; 4096];
+                let size = std::io::Read::read(&mut stream, &mut buffer).unwrap();
+                requests.push(String::from_utf8_lossy(&buffer[..size]).to_string());
+                let body = format!("{{\"status\": \"{}\"}}", response);
+                std::io::Write::write_all(&mut stream, format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n{}", response, body.len(), body
+                ).as_bytes()).unwrap();
+            }
+            requests
+        });
+
+        let dir = std::env::temp_dir().join(format!("nauman-http-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Http
+cwd: {dir}
+policy: always
+env:
+  SCHEME: ftp
+tasks:
+  - id: create
+    http:
+      method: post
+      url: http://127.0.0.1:{port}/items
+      json:
+        job: ${{{{ job.id }}}}
+      expect: [201]
+  - id: check
+    run: echo "${{{{ tasks.create.outputs.HTTP_STATUS }}}} $HTTP_BODY"
+  - id: health
+    http:
+      url: http://127.0.0.1:{port}/health
+  - id: invalid
+    http:
+      url: ${{{{ env.SCHEME }}}}://127.0.0.1:{port}/
+logging:
+  - type: file
+    output: {dir}/output.log
+"#, dir = dir.display(), port = port)).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 1);
+
+        let requests = server.join().unwrap();
+        let output = std::fs::read_to_string(dir.join("output.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(requests[0].starts_with("POST /items HTTP/1.1\r\n"));
+        assert!(requests[0].contains("Content-Type: application/json"));
+        assert!(requests[0].ends_with("{\"job\":\"job\"}"));
+        assert!(requests[1].starts_with("GET /health HTTP/1.1\r\n"));
+        assert!(output.contains("> POST http://127.0.0.1"));
+        assert!(output.contains("< 201 Created"));
+        assert!(output.contains("201 {\"status\": \"201 Created\"}"));
+        assert!(output.contains("< 503 Service Unavailable"));
+        assert!(output.contains("Invalid url \"ftp://127.0.0.1"));
+    }
+
+    #[test]
+    fn script_task_test() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("nauman-script-task-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("scripts")).unwrap();
+        let script = dir.join("scripts").join("greet.sh");
+        std::fs::write(&script, "#!/bin/sh\necho \"Hello $1 from $(basename \"$PWD\")\"\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Script
+cwd: {dir}
+tasks:
+  - id: greet
+    script: ./scripts/greet.sh
+    args: ["big world"]
+    cwd: scripts
+logging:
+  - type: file
+    output: {dir}/output.log
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 0);
+
+        let output = std::fs::read_to_string(dir.join("output.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(output.contains(&format!("$ /bin/sh {} 'big world'", script.display())), "{}", output);
+        assert!(output.contains("Hello big world from scripts"));
+    }
+
+    #[test]
+    fn exec_task_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-exec-task-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Exec
+cwd: {dir}
+env:
+  FILE_NAME: "report $(date); done.txt"
+tasks:
+  - id: create
+    command: [touch, "${{FILE_NAME}}"]
+  - id: list
+    command: [ls, "${{FILE_NAME}}", "${{{{ job.id }}}}.yml"]
+  - id: literal
+    command: [echo, "${{FILE_NAME}}"]
+    expand_env: false
+logging:
+  - type: file
+    output: {dir}/output.log
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 0);
+
+        let created = dir.join("report $(date); done.txt").is_file();
+        let output = std::fs::read_to_string(dir.join("output.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(created);
+        assert!(output.contains("$ ls 'report $(date); done.txt' job.yml"), "{}", output);
+        assert!(output.contains("\n${FILE_NAME}\n"));
+    }
+
+    #[test]
+    fn missing_program_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-missing-program-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Missing
+policy: always
+cwd: {dir}
+tasks:
+  - id: missing
+    command: [nauman-missing-program, "--version"]
+    hooks:
+      on_failure:
+        - run: echo "reported $NAUMAN_PREV_CODE"
+  - id: after
+    run: echo "still running"
+logging:
+  - type: file
+    output: {dir}/output.log
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 127);
+
+        let output = std::fs::read_to_string(dir.join("output.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(output.contains("Failed to execute command: nauman-missing-program --version. Error:"), "{}", output);
+        assert!(output.contains("reported 127"), "{}", output);
+        assert!(output.contains("still running"), "{}", output);
+    }
+
+    #[test]
+    fn unresolved_template_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-unresolved-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Unresolved
+cwd: {dir}
+tasks:
+  - id: greet
+    run: echo ${{{{ MISSING }}}}
+  - id: cleanup
+    policy: always
+    run: echo cleaned up
+logging:
+  - type: file
+    output: {dir}/output.log
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 2);
+
+        let output = std::fs::read_to_string(dir.join("output.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(output.contains("failed to render field \"name\": Unresolved variable \"MISSING\""), "{}", output);
+        assert!(output.contains("cleaned up"), "{}", output);
+    }
+
+    #[test]
+    fn sub_job_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-sub-job-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        std::fs::write(dir.join("shared").join("backup.yml"), r#"
+name: Backup
+env:
+  TARGET: local
+  BUCKET: default
+tasks:
+  - id: dump
+    run: echo "Dumping to $TARGET/$BUCKET as $PARENT_VALUE"
+  - id: verify
+    run: exit 3
+"#).unwrap();
+        std::fs::write(dir.join("shared").join("loop.yml"), "name: Loop\ntasks:\n  - job: ../job.yml\n").unwrap();
+        std::fs::write(dir.join("shared").join("slow.yml"), r#"
+name: Slow
+tasks:
+  - id: first
+    run: sleep 0.6
+  - id: second
+    run: sleep 0.6 && echo "second finished"
+"#).unwrap();
+        std::fs::write(dir.join("shared").join("secret.yml"), "name: Secret\nsecrets:\n  file: missing.env\ntasks:\n  - run: \"true\"\n").unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Parent
+cwd: {dir}
+env:
+  PARENT_VALUE: parent
+tasks:
+  - id: backup
+    job: ./shared/backup.yml
+    env:
+      TARGET: s3
+  - id: loop
+    job: ./shared/loop.yml
+    policy: always
+  - id: slow
+    job: ./shared/slow.yml
+    timeout: 1
+    policy: always
+  - id: secret
+    job: ./shared/secret.yml
+    policy: always
+  - id: after
+    run: echo "still running"
+    policy: always
+logging:
+  - type: file
+    output: output.log
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 3);
+
+        let run_dir = std::fs::read_dir(dir.join("logs")).unwrap()
+            .flatten()
+            .find(|entry| entry.path().is_dir())
+            .unwrap().path();
+        let output = std::fs::read_to_string(run_dir.join("output.log")).unwrap();
+        let history = std::fs::read_to_string(dir.join("logs").join("history.jsonl")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(output.contains("Dumping to s3/default as parent"), "{}", output);
+        assert!(output.contains(&format!(
+            "Recursive sub job: {job} -> {dir}/shared/loop.yml -> {job}", job = job_path.display(), dir = dir.display()
+        )), "{}", output);
+        assert!(output.contains("Task \"sleep 0.6 && echo \"second finished\"\" timed out"), "{}", output);
+        assert!(!output.contains("second finished\n"), "{}", output);
+        assert!(output.contains("Failed to load secrets file"), "{}", output);
+        assert!(output.contains("still running"), "{}", output);
+        assert_eq!(output.matches("Summary:").count(), 1, "{}", output);
+        assert_eq!(history.lines().count(), 1);
+    }
+
+    #[test]
+    fn recursive_sub_job_test() {
+        let dir = std::env::temp_dir().join(format!("nauman-recursive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let job_path = dir.join("job.yml");
+        std::fs::write(&job_path, format!(r#"
+name: Recursive
+tasks:
+  - id: itself
+    job: ./job.yml
+logging:
+  - type: file
+    output: {dir}/output.log
+"#, dir = dir.display())).unwrap();
+
+        let opts = Opts {
+            job: Some(job_path.to_str().unwrap().to_string()),
+            log_dir: Some(dir.join("logs").to_str().unwrap().to_string()),
+            ..Opts::default()
+        };
+        assert_eq!(process(opts).expect("Failed to execute job"), 2);
+
+        let output = std::fs::read_to_string(dir.join("output.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(output.contains(&format!("Recursive sub job: {job} -> {job}", job = job_path.display())), "{}", output);
+        assert_eq!(output.matches("Task: ").count(), 1, "{}", output);
+    }
+
+    #[test_case("conditions.yml")]
+    #[test_case("exec.yml")]
+    #[test_case("hello-world.yml")]
+    #[test_case("http.yml")]
+    #[test_case("includes.yml")]
+    #[test_case("parallel.yml")]
+    #[test_case("templating.yml")]
+    fn validate_tests(example: &str) {
+        let example_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("examples")
+            .join(example).to_str().unwrap().to_string();
+
+        assert_eq!(validate(&example_path).expect("Failed to validate example job"), 0);
+    }
+
+    #[test]
+    fn schema_test() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("schema.json");
+        let expected = std::fs::read_to_string(path).expect("Failed to read schema.json");
+        assert_eq!(
+            schema_json().unwrap().trim(), expected.trim(),
+            "schema.json is out of date, regenerate it with `cargo run -- schema > schema.json`"
+        );
+    }
 }
@@ -0,0 +1,228 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use flate2::{write::GzEncoder, Compression};
+use crate::config::Retention;
+
+/// Format of the timestamp within the run ids
+const RUN_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+/// Extension of the compressed run directories
+const ARCHIVE_EXTENSION: &str = ".tar.gz";
+
+/// Log directory (or its compressed archive) of a previous run
+#[derive(Debug, Clone)]
+pub struct RunEntry {
+    pub path: PathBuf,
+    pub run_id: String,
+    pub started_at: NaiveDateTime,
+    pub compressed: bool,
+    /// Total size of the files in bytes
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionAction {
+    Delete,
+    Compress,
+}
+
+impl Display for RetentionAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RetentionAction::Delete => write!(f, "delete"),
+            RetentionAction::Compress => write!(f, "compress"),
+        }
+    }
+}
+
+/// Returns the total size of the files within the path
+fn path_size(path: &Path) -> u64 {
+    if path.is_dir() {
+        std::fs::read_dir(path).into_iter().flatten().flatten()
+            .map(|entry| path_size(&entry.path()))
+            .sum()
+    } else {
+        path.metadata().map(|metadata| metadata.len()).unwrap_or(0)
+    }
+}
+
+/// Finds the runs of the given job within the log directory (newest first)
+pub fn find_runs(log_root: &Path, job_id: &str) -> Result<Vec<RunEntry>> {
+    if !log_root.is_dir() {
+        return Ok(Vec::new());
+    }
+    let prefix = format!("{}_", job_id);
+    let mut runs = Vec::new();
+    for entry in std::fs::read_dir(log_root)? {
+        let path = entry?.path();
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let (run_id, compressed) = match name.strip_suffix(ARCHIVE_EXTENSION) {
+            Some(run_id) => (run_id.to_string(), true),
+            None if path.is_dir() => (name.clone(), false),
+            None => continue,
+        };
+        let started_at = match run_id.strip_prefix(&prefix)
+            .and_then(|timestamp| NaiveDateTime::parse_from_str(timestamp, RUN_TIMESTAMP_FORMAT).ok()) {
+            Some(started_at) => started_at,
+            None => continue,
+        };
+        runs.push(RunEntry { size: path_size(&path), path, run_id, started_at, compressed });
+    }
+    runs.sort_by_key(|run| std::cmp::Reverse(run.started_at));
+    Ok(runs)
+}
+
+/// Determines which of the runs (newest first) should be deleted or compressed.
+/// The run with the given id (i.e. the current one) is always kept.
+pub fn plan<'a>(
+    retention: &Retention,
+    runs: &'a [RunEntry],
+    now: NaiveDateTime,
+    keep: Option<&str>,
+) -> Vec<(&'a RunEntry, RetentionAction)> {
+    let mut actions = Vec::new();
+    let mut kept = 0;
+    let mut total_size = 0;
+    for run in runs {
+        let age = (now - run.started_at).to_std().unwrap_or_default();
+        let expired = retention.keep_last.map(|keep_last| kept >= keep_last).unwrap_or(false)
+            || retention.max_age.map(|max_age| age > max_age.as_duration()).unwrap_or(false)
+            || retention.max_size.map(|max_size| total_size + run.size > max_size.as_bytes()).unwrap_or(false);
+
+        if expired && keep != Some(run.run_id.as_str()) {
+            actions.push((run, RetentionAction::Delete));
+            continue;
+        }
+        kept += 1;
+        total_size += run.size;
+        if !run.compressed && retention.compress_after.map(|after| age > after.as_duration()).unwrap_or(false) {
+            actions.push((run, RetentionAction::Compress));
+        }
+    }
+    actions
+}
+
+/// Writes the directory to a gzip compressed tar archive, in which it is stored under the given name
+fn compress_dir(dir: &Path, name: &str, archive: &Path) -> std::io::Result<()> {
+    let file = std::fs::File::create(archive)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    builder.append_dir_all(name, dir)?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Deletes or compresses the run
+pub fn apply(run: &RunEntry, action: RetentionAction) -> Result<()> {
+    match action {
+        RetentionAction::Delete if run.path.is_dir() => std::fs::remove_dir_all(&run.path)?,
+        RetentionAction::Delete => std::fs::remove_file(&run.path)?,
+        RetentionAction::Compress => {
+            let parent = run.path.parent().ok_or_else(|| anyhow!("Invalid run directory: {:?}", run.path))?;
+            let archive = parent.join(format!("{}{}", run.run_id, ARCHIVE_EXTENSION));
+            if let Err(e) = compress_dir(&run.path, &run.run_id, &archive) {
+                // Keep the run directory and remove the partially written archive
+                let _ = std::fs::remove_file(&archive);
+                return Err(anyhow!("Failed to compress run directory: {:?}. Error: {}", run.path, e));
+            }
+            std::fs::remove_dir_all(&run.path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use chrono::NaiveDateTime;
+    use test_case::test_case;
+    use crate::common::{ByteSize, HumanDuration};
+    use crate::config::Retention;
+    use crate::retention::{apply, find_runs, plan, RetentionAction, RunEntry};
+
+    fn runs() -> Vec<RunEntry> {
+        (0..5).map(|day| {
+            let run_id = format!("job_2021-11-{:02}T12:00:00", 10 - day);
+            RunEntry {
+                path: PathBuf::from(&run_id),
+                started_at: NaiveDateTime::parse_from_str(&run_id[4..], "%Y-%m-%dT%H:%M:%S").unwrap(),
+                run_id,
+                compressed: false,
+                size: 100,
+            }
+        }).collect()
+    }
+
+    #[test_case(Retention { keep_last: Some(2), ..Retention::default() }, &["delete", "delete", "delete"] ; "keep last")]
+    #[test_case(Retention { max_age: Some(HumanDuration::from_secs(2 * 86400 + 1)), ..Retention::default() }, &["delete", "delete"] ; "max age")]
+    #[test_case(Retention { max_size: Some(ByteSize(350)), ..Retention::default() }, &["delete", "delete"] ; "max size")]
+    #[test_case(Retention { keep_last: Some(4), compress_after: Some(HumanDuration::from_secs(86400 * 2)), ..Retention::default() }, &["compress", "delete"] ; "compress")]
+    fn test_plan(retention: Retention, expected: &[&str]) {
+        let runs = runs();
+        let now = NaiveDateTime::parse_from_str("2021-11-10T12:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        let actions: Vec<String> = plan(&retention, &runs, now, None).iter()
+            .map(|(_, action)| action.to_string())
+            .collect();
+        assert_eq!(actions, expected);
+    }
+
+    #[test]
+    fn test_plan_keeps_current_run() {
+        let runs = runs();
+        let now = NaiveDateTime::parse_from_str("2021-11-10T12:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        let retention = Retention { keep_last: Some(0), ..Retention::default() };
+        let actions = plan(&retention, &runs, now, Some(&runs[0].run_id));
+        assert_eq!(actions.len(), 4);
+        assert!(actions.iter().all(|(run, action)| run.run_id != runs[0].run_id && *action == RetentionAction::Delete));
+    }
+
+    #[test]
+    fn test_find_runs() {
+        let dir = std::env::temp_dir().join(format!("nauman-retention-{}", std::process::id()));
+        for name in ["job_2021-11-09T12:00:00", "job_2021-11-10T12:00:00", "other_2021-11-10T12:00:00", "job_logs"] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        std::fs::write(dir.join("job_2021-11-08T12:00:00.tar.gz"), "archive").unwrap();
+        std::fs::write(dir.join("history.jsonl"), "").unwrap();
+
+        let runs = find_runs(&dir, "job").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let run_ids: Vec<&str> = runs.iter().map(|run| run.run_id.as_str()).collect();
+        assert_eq!(run_ids, vec!["job_2021-11-10T12:00:00", "job_2021-11-09T12:00:00", "job_2021-11-08T12:00:00"]);
+        assert!(runs[2].compressed);
+        assert_eq!(runs[2].size, 7);
+    }
+
+    #[test]
+    fn test_compress_run() {
+        let dir = std::env::temp_dir().join(format!("nauman-compress-{}", std::process::id()));
+        let run_id = "job_2021-11-10T12:00:00";
+        std::fs::create_dir_all(dir.join(run_id).join("tasks")).unwrap();
+        std::fs::write(dir.join(run_id).join("full.log"), "hello\n").unwrap();
+        std::fs::write(dir.join(run_id).join("tasks").join("build.log"), "built\n").unwrap();
+
+        let run = find_runs(&dir, "job").unwrap().remove(0);
+        apply(&run, RetentionAction::Compress).unwrap();
+        let runs = find_runs(&dir, "job").unwrap();
+        let archive = std::fs::File::open(&runs[0].path).unwrap();
+        let mut entries: Vec<(String, String)> = tar::Archive::new(flate2::read::GzDecoder::new(archive))
+            .entries().unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.header().entry_type().is_file())
+            .map(|mut entry| {
+                let mut contents = String::new();
+                std::io::Read::read_to_string(&mut entry, &mut contents).unwrap();
+                (entry.path().unwrap().to_string_lossy().to_string(), contents)
+            })
+            .collect();
+        entries.sort();
+        let removed = !dir.join(run_id).exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(runs[0].compressed && removed);
+        assert_eq!(entries, vec![
+            (format!("{}/full.log", run_id), "hello\n".to_string()),
+            (format!("{}/tasks/build.log", run_id), "built\n".to_string()),
+        ]);
+    }
+}
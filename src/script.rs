@@ -0,0 +1,100 @@
+use std::io::{BufRead, BufReader};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use crate::config::{Script, ShellType};
+
+/// Whether the file has any of the executable permission bits set
+pub fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Reads the interpreter and its optional argument from the shebang line of the script (e.g. `#!/usr/bin/env python3`).
+/// Just like the kernel does, everything after the interpreter is passed as a single argument.
+pub fn read_shebang(path: &Path) -> Result<Option<Vec<String>>> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow!("Failed to open script: {:?}. Error: {}", path, e))?;
+    let mut line = Vec::new();
+    BufReader::new(file).read_until(b'\n', &mut line)?;
+    let line = String::from_utf8_lossy(&line);
+    let shebang = match line.strip_prefix("#!") {
+        Some(shebang) => shebang.trim(),
+        None => return Ok(None),
+    };
+    let mut parts = shebang.splitn(2, char::is_whitespace);
+    let interpreter = match parts.next().filter(|interpreter| !interpreter.is_empty()) {
+        Some(interpreter) => interpreter.to_string(),
+        None => return Ok(None),
+    };
+    Ok(Some(std::iter::once(interpreter)
+        .chain(parts.next().map(str::trim).filter(|arg| !arg.is_empty()).map(String::from))
+        .collect()))
+}
+
+/// Returns the program and its arguments running the script at the given path.
+/// An explicitly configured shell takes precedence over the shebang, and scripts without one are executed directly.
+pub fn resolve(
+    script: &Script,
+    path: &Path,
+    default_shell: &ShellType,
+    default_shell_path: Option<&String>,
+) -> Result<(String, Vec<String>)> {
+    let path = path.to_string_lossy().to_string();
+    let mut command = if script.has_shell() {
+        let shell = script.shell.clone().unwrap_or_else(|| default_shell.clone());
+        let shell_path = script.shell_path.as_ref()
+            .or_else(|| if shell == *default_shell { default_shell_path } else { None });
+        vec![shell.executable(shell_path)?, path]
+    } else {
+        match read_shebang(Path::new(&path))? {
+            Some(mut interpreter) => {
+                interpreter.push(path);
+                interpreter
+            }
+            None => vec![path],
+        }
+    };
+    command.extend(script.args.iter().cloned());
+    let program = command.remove(0);
+    Ok((program, command))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use test_case::test_case;
+    use crate::config::{Script, ShellType};
+    use crate::script::{read_shebang, resolve};
+
+    fn write_script(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("nauman-script-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test_case("#!/bin/bash\necho", Some(vec!["/bin/bash"]) ; "interpreter")]
+    #[test_case("#! /usr/bin/env python3 -u\nprint()", Some(vec!["/usr/bin/env", "python3 -u"]) ; "argument")]
+    #[test_case("echo", None ; "no shebang")]
+    fn test_read_shebang(contents: &str, expected: Option<Vec<&str>>) {
+        let path = write_script("shebang", contents);
+        let shebang = read_shebang(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(shebang, expected.map(|parts| parts.iter().map(|part| part.to_string()).collect()));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let path = write_script("resolve.py", "#!/usr/bin/env python3\nprint()");
+        let script_path = path.to_string_lossy().to_string();
+        let mut script = Script { script: script_path.clone(), args: vec!["--dry".to_string()], shell: None, shell_path: None };
+        let from_shebang = resolve(&script, &path, &ShellType::Sh, None).unwrap();
+        script.shell = Some(ShellType::Bash);
+        let from_shell = resolve(&script, &path, &ShellType::Sh, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(from_shebang, ("/usr/bin/env".to_string(), vec!["python3".to_string(), script_path.clone(), "--dry".to_string()]));
+        assert_eq!(from_shell, ("bash".to_string(), vec![script_path, "--dry".to_string()]));
+    }
+}
@@ -0,0 +1,253 @@
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use crate::config::SummaryFormat;
+use crate::execution::JobStatus;
+use crate::history::{RunRecord, TaskRecord};
+
+/// Renders the summary of a run in the given format
+pub fn render(format: SummaryFormat, record: &RunRecord) -> Result<String> {
+    Ok(match format {
+        SummaryFormat::Json => serde_json::to_string_pretty(record)?,
+        SummaryFormat::Junit => junit(record),
+        SummaryFormat::Markdown => markdown(record),
+    })
+}
+
+/// Writes the summary of a run in the given format to a file
+pub fn write(format: SummaryFormat, record: &RunRecord, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, render(format, record)?)
+        .map_err(|e| anyhow!("Failed to write summary: {:?}. Error: {}", path, e))
+}
+
+fn job_status(record: &RunRecord) -> String {
+    format!("{:?}", record.status).to_lowercase()
+}
+
+fn format_duration(duration: Option<f64>) -> String {
+    duration.map(|d| format!("{:.1}", d)).unwrap_or_else(|| "-".to_string())
+}
+
+/// Describes the result of a hook on a single line
+fn hook_line(hook: &TaskRecord) -> String {
+    format!(
+        "Hook: {} ({}, exit code {}, {} s)",
+        hook.name, hook.status, hook.exit_code, format_duration(hook.duration)
+    )
+}
+
+/// Describes the results of the sub job commands run by a task, a line per command (indented by their depth)
+fn nested_lines(task: &TaskRecord, depth: usize, lines: &mut Vec<String>) {
+    for nested in &task.nested {
+        lines.push(format!(
+            "{}↳ {}: {} ({}, exit code {}, {} s)",
+            "  ".repeat(depth), if nested.is_hook { "Hook" } else { "Task" },
+            nested.name, nested.status, nested.exit_code, format_duration(nested.duration)
+        ));
+        nested_lines(nested, depth + 1, lines);
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Renders a JUnit XML report with a test case per main task.
+/// The results of the hooks are listed in the system output of the task they were executed for,
+/// or in the system output of the test suite for the job level hooks.
+/// The results of a sub job are listed in the system output of the task running it.
+fn junit(record: &RunRecord) -> String {
+    let tasks: Vec<&TaskRecord> = record.tasks.iter().filter(|task| !task.is_hook).collect();
+    let failures = tasks.iter().filter(|task| matches!(task.status.as_str(), "failure" | "timed_out")).count();
+    let skipped = tasks.iter().filter(|task| matches!(task.status.as_str(), "skipped" | "deselected" | "aborted")).count();
+    let time = record.duration().num_milliseconds() as f64 / 1000.0;
+    let hooks_of = |focus_id: Option<&String>| -> Vec<String> {
+        record.tasks.iter()
+            .filter(|task| task.is_hook && task.focus_id.as_ref() == focus_id)
+            .map(|hook| escape_xml(&hook_line(hook)))
+            .collect()
+    };
+
+    let mut lines = vec![
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string(),
+        format!(
+            r#"<testsuites name="{}" tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+            escape_xml(&record.job_name), tasks.len(), failures, skipped, time
+        ),
+        format!(
+            r#"  <testsuite name="{}" id="{}" tests="{}" failures="{}" errors="0" skipped="{}" time="{:.3}" timestamp="{}" hostname="{}">"#,
+            escape_xml(&record.job_name), escape_xml(&record.run_id), tasks.len(), failures, skipped, time,
+            record.started_at.format("%Y-%m-%dT%H:%M:%S"), escape_xml(record.host.as_deref().unwrap_or("localhost"))
+        ),
+    ];
+    for task in tasks {
+        lines.push(format!(
+            r#"    <testcase name="{}" classname="{}" time="{:.3}">"#,
+            escape_xml(&task.name), escape_xml(&record.job_id), task.duration.unwrap_or_default()
+        ));
+        match task.status.as_str() {
+            "failure" => lines.push(format!(
+                r#"      <failure message="Task exited with code {}" type="failure"/>"#, task.exit_code
+            )),
+            "timed_out" => lines.push(r#"      <failure message="Task timed out" type="timed_out"/>"#.to_string()),
+            "skipped" | "deselected" | "aborted" => lines.push(format!(r#"      <skipped message="{}"/>"#, task.status)),
+            _ => {}
+        }
+        let mut output = Vec::new();
+        nested_lines(task, 0, &mut output);
+        let output: Vec<String> = output.iter().map(|line| escape_xml(line)).chain(hooks_of(Some(&task.command_id))).collect();
+        if !output.is_empty() {
+            lines.push(format!("      <system-out>{}</system-out>", output.join("\n")));
+        }
+        lines.push("    </testcase>".to_string());
+    }
+    let hooks = hooks_of(None);
+    if !hooks.is_empty() {
+        lines.push(format!("    <system-out>{}</system-out>", hooks.join("\n")));
+    }
+    lines.push("  </testsuite>".to_string());
+    lines.push("</testsuites>".to_string());
+    lines.join("\n") + "\n"
+}
+
+fn escape_markdown(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+/// Renders a Markdown table with the results of all the commands (including the sub job commands)
+fn markdown(record: &RunRecord) -> String {
+    let mut lines = vec![
+        format!("## Summary: {}", escape_markdown(&record.job_name)),
+        String::new(),
+        format!(
+            "{} Run `{}` finished with status **{}** (exit code {}) in {} s.",
+            match record.status {
+                JobStatus::Success => "✅",
+                JobStatus::Failed => "❌",
+                JobStatus::Skipped => "⏭️",
+            },
+            record.run_id, job_status(record), record.exit_code, record.duration().num_seconds()
+        ),
+        String::new(),
+        "| Task | Name | Status | Exit code | Time (in s) | Attempts |".to_string(),
+        "| --- | --- | --- | --- | --- | --- |".to_string(),
+    ];
+    markdown_rows(&record.tasks, 0, &mut lines);
+    lines.join("\n") + "\n"
+}
+
+/// Adds a table row per command, the sub job commands are listed below the task running them
+fn markdown_rows(tasks: &[TaskRecord], depth: usize, lines: &mut Vec<String>) {
+    for task in tasks {
+        lines.push(format!(
+            "| {} | {}{} | {} | {} | {} | {} |",
+            if task.is_hook { "hook" } else { "task" },
+            "↳ ".repeat(depth),
+            escape_markdown(&task.name),
+            task.status,
+            task.exit_code,
+            format_duration(task.duration),
+            if task.attempts > 0 { task.attempts.to_string() } else { "-".to_string() },
+        ));
+        markdown_rows(&task.nested, depth + 1, lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{self, SummaryFormat};
+    use crate::execution::{ExecutionResult, JobResult, JobStatus, RunInfo, TaskOutcome};
+    use crate::flow::Flow;
+    use crate::history::RunRecord;
+    use crate::summary::render;
+
+    fn record() -> RunRecord {
+        let job: config::Job = serde_yaml::from_str(r#"
+name: Build & Test
+tasks:
+  - id: build
+    run: "true"
+  - id: test
+    name: Test <all>
+    run: "false"
+    hooks:
+      on_failure:
+        - id: report
+          name: Report
+          run: "true"
+  - id: deploy
+    name: Deploy
+    run: "true"
+"#).unwrap();
+        let flow = Flow::parse(&job).unwrap();
+        let run = RunInfo::new("build", "Build & Test");
+        let result = JobResult {
+            status: JobStatus::Failed,
+            exit_code: 1,
+            results: vec![
+                ("build".to_string(), ExecutionResult {
+                    attempts: 1,
+                    nested: vec![ExecutionResult {
+                        name: Some("Compile".to_string()),
+                        attempts: 1,
+                        ..ExecutionResult::new("compile".to_string(), None)
+                    }],
+                    ..ExecutionResult::new("build".to_string(), None)
+                }),
+                ("test".to_string(), ExecutionResult { exit_code: 1, attempts: 1, ..ExecutionResult::new("test".to_string(), None) }),
+                ("report".to_string(), ExecutionResult { attempts: 1, ..ExecutionResult::new("report".to_string(), Some("test".to_string())) }),
+                ("deploy".to_string(), TaskOutcome::deselected(&"deploy".to_string()).result),
+            ],
+        };
+        RunRecord::new(&flow, &run, None, &result)
+    }
+
+    #[test]
+    fn test_junit() {
+        let xml = render(SummaryFormat::Junit, &record()).unwrap();
+        assert!(xml.contains(r#"<testsuites name="Build &amp; Test" tests="3" failures="1" skipped="1""#));
+        assert!(xml.contains(r#"<testcase name="Test &lt;all&gt;" classname="build""#));
+        assert!(xml.contains(r#"<failure message="Task exited with code 1" type="failure"/>"#));
+        assert!(xml.contains("<system-out>Hook: Report (success, exit code 0, - s)</system-out>"));
+        assert!(xml.contains(r#"<skipped message="deselected"/>"#));
+        assert!(xml.contains("<system-out>↳ Task: Compile (success, exit code 0, - s)</system-out>"));
+        assert_eq!(xml.matches("<testcase").count(), 3);
+    }
+
+    #[test]
+    fn test_markdown() {
+        let markdown = render(SummaryFormat::Markdown, &record()).unwrap();
+        assert!(markdown.starts_with("## Summary: Build & Test\n"));
+        assert!(markdown.contains("❌ Run `"));
+        assert!(markdown.contains("**failed** (exit code 1)"));
+        assert!(markdown.contains("| task | ↳ Compile | success | 0 | - | 1 |"));
+        assert!(markdown.contains("| task | Test <all> | failure | 1 | - | 1 |"));
+        assert!(markdown.contains("| hook | Report | success | 0 | - | 1 |"));
+        assert!(markdown.contains("| task | Deploy | deselected | 0 | - | - |"));
+    }
+
+    #[test]
+    fn test_json() {
+        let json: serde_json::Value = serde_json::from_str(&render(SummaryFormat::Json, &record()).unwrap()).unwrap();
+        assert_eq!(json["status"], "failed");
+        assert_eq!(json["tasks"][2]["focus_id"], "test");
+        assert_eq!(json["tasks"][3]["status"], "deselected");
+        assert_eq!(json["tasks"][0]["nested"][0]["command_id"], "compile");
+    }
+
+    #[test]
+    fn test_markdown_status_icon() {
+        // The job may be configured to exit with zero on failure
+        let mut record = record();
+        record.exit_code = 0;
+        let markdown = render(SummaryFormat::Markdown, &record).unwrap();
+        assert!(markdown.contains("❌ Run `"));
+        assert!(markdown.contains("**failed** (exit code 0)"));
+    }
+}
@@ -0,0 +1,334 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use anyhow::{anyhow, Result};
+use crate::expression::Scope;
+
+const TEMPLATE_START: &str = "${{";
+const TEMPLATE_END: &str = "}}";
+
+/// Built-in variables of the job and the current run
+const JOB_BUILTINS: &[&str] = &["job.id", "job.name", "run.id", "run.date", "run.time", "run.timestamp", "run.log_dir"];
+/// Built-in variables of the current task
+const TASK_BUILTINS: &[&str] = &["task.id", "task.name"];
+
+/// Filter applied to a template value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Replaces an unresolved or empty value with the given one
+    Default(String),
+    Upper,
+    Lower,
+    Trim,
+    /// Joins the value with the given path
+    Join(String),
+}
+
+impl Filter {
+    fn parse(name: &str, argument: Option<String>) -> Result<Self> {
+        let required = |argument: Option<String>| {
+            argument.ok_or_else(|| anyhow!("Filter \"{}\" requires an argument", name))
+        };
+        Ok(match name {
+            "default" => Filter::Default(required(argument)?),
+            "upper" => Filter::Upper,
+            "lower" => Filter::Lower,
+            "trim" => Filter::Trim,
+            "join" => Filter::Join(required(argument)?),
+            _ => return Err(anyhow!("Unknown filter \"{}\"", name)),
+        })
+    }
+
+    fn apply(&self, value: Option<String>) -> Option<String> {
+        match self {
+            Filter::Default(default) => match value {
+                Some(value) if !value.is_empty() => Some(value),
+                _ => Some(default.clone()),
+            },
+            Filter::Upper => value.map(|v| v.to_uppercase()),
+            Filter::Lower => value.map(|v| v.to_lowercase()),
+            Filter::Trim => value.map(|v| v.trim().to_string()),
+            Filter::Join(path) => value.map(|v| PathBuf::from(v).join(path).to_string_lossy().to_string()),
+        }
+    }
+}
+
+/// Value a template expression starts with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Literal(String),
+    Variable(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Text(String),
+    Expression(Operand, Vec<Filter>),
+}
+
+/// A text containing `${{ variable | filter }}` expressions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Whether the text contains any template expressions
+    pub fn is_template(text: &str) -> bool {
+        text.contains(TEMPLATE_START)
+    }
+
+    /// Returns the variables referenced in the template
+    pub fn variables(&self) -> Vec<&String> {
+        self.segments.iter()
+            .filter_map(|segment| match segment {
+                Segment::Expression(Operand::Variable(name), _) => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the ids of the tasks referenced in the template
+    pub fn task_references(&self) -> Vec<&str> {
+        self.variables().into_iter()
+            .filter_map(|name| match name.splitn(4, '.').collect::<Vec<_>>().as_slice() {
+                ["tasks", id, ..] => Some(*id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Checks that the variables can be resolved on rendering (unless they have a default value),
+    /// i.e. that they are environment variables, task outputs or known built-in variables.
+    /// The task variables can only be resolved within a task.
+    pub fn check_variables(&self, in_task: bool) -> Result<()> {
+        for segment in &self.segments {
+            let name = match segment {
+                Segment::Expression(Operand::Variable(name), filters)
+                    if !filters.iter().any(|filter| matches!(filter, Filter::Default(_))) => name,
+                _ => continue,
+            };
+            let valid = match name.splitn(4, '.').collect::<Vec<_>>().as_slice() {
+                ["env", _] | [_] => true,
+                ["tasks", _, "outputs", _] => in_task,
+                ["task", _] => in_task && TASK_BUILTINS.contains(&name.as_str()),
+                ["job" | "run", _] => JOB_BUILTINS.contains(&name.as_str()),
+                _ => false,
+            };
+            if !valid {
+                return Err(anyhow!("Unknown variable \"{}\"", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the template resolving the variables within the given scope
+    pub fn render(&self, scope: &impl Scope) -> Result<String> {
+        let mut result = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => result.push_str(text),
+                Segment::Expression(operand, filters) => {
+                    let value = match operand {
+                        Operand::Literal(value) => Some(value.clone()),
+                        Operand::Variable(name) => resolve_variable(name, scope),
+                    };
+                    let value = filters.iter().fold(value, |value, filter| filter.apply(value));
+                    match (value, operand) {
+                        (Some(value), _) => result.push_str(&value),
+                        (None, Operand::Variable(name)) => return Err(anyhow!("Unresolved variable \"{}\"", name)),
+                        (None, Operand::Literal(_)) => unreachable!(),
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Renders a text if it contains template expressions
+pub fn render(text: &str, scope: &impl Scope) -> Result<String> {
+    if Template::is_template(text) {
+        Template::from_str(text)?.render(scope)
+    } else {
+        Ok(text.to_string())
+    }
+}
+
+/// Resolves a variable path within the given scope
+fn resolve_variable(name: &str, scope: &impl Scope) -> Option<String> {
+    let parts: Vec<&str> = name.splitn(4, '.').collect();
+    match parts.as_slice() {
+        ["env", name] => scope.env(name),
+        ["tasks", id, "outputs", name] => scope.task(id).and_then(|result| result.outputs.get(name).cloned()),
+        ["job" | "run" | "task", _] => scope.builtin(name),
+        [name] => scope.env(name),
+        _ => None,
+    }
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find(TEMPLATE_START) {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let inner_start = start + TEMPLATE_START.len();
+            let end = rest[inner_start..].find(TEMPLATE_END)
+                .ok_or_else(|| anyhow!("Unterminated template expression in \"{}\"", s))?;
+            let inner = &rest[inner_start..inner_start + end];
+            segments.push(parse_expression(inner)
+                .map_err(|e| anyhow!("Invalid template expression \"{}\": {}", inner.trim(), e))?);
+            rest = &rest[inner_start + end + TEMPLATE_END.len()..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+
+        Ok(Template { segments })
+    }
+}
+
+/// Parses a quoted string literal
+fn parse_literal(text: &str) -> Option<String> {
+    let text = text.trim();
+    let quote = text.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    if text.len() >= 2 && text.ends_with(quote) {
+        Some(text[1..text.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+/// Parses an expression of form `operand | filter | filter('argument')`
+fn parse_expression(text: &str) -> Result<Segment> {
+    let mut parts = split_filters(text).into_iter();
+    let operand = parts.next().unwrap_or_default();
+    let operand = operand.trim();
+    let operand = if let Some(literal) = parse_literal(operand) {
+        Operand::Literal(literal)
+    } else if !operand.is_empty() && operand.chars().all(|c| c.is_alphanumeric() || "_-.".contains(c)) {
+        Operand::Variable(operand.to_string())
+    } else {
+        return Err(anyhow!("Invalid variable \"{}\"", operand));
+    };
+
+    let mut filters = Vec::new();
+    for part in parts {
+        let part = part.trim();
+        let (name, argument) = match part.find('(') {
+            Some(open) if part.ends_with(')') => {
+                let argument = parse_literal(&part[open + 1..part.len() - 1])
+                    .ok_or_else(|| anyhow!("Filter arguments should be quoted strings"))?;
+                (part[..open].trim(), Some(argument))
+            }
+            Some(_) => return Err(anyhow!("Unclosed filter arguments")),
+            None => (part, None),
+        };
+        filters.push(Filter::parse(name, argument)?);
+    }
+
+    Ok(Segment::Expression(operand, filters))
+}
+
+/// Splits the expression on the filter separators which are not within quotes
+fn split_filters(text: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quote = None;
+    for c in text.chars() {
+        match (c, quote) {
+            ('|', None) => parts.push(String::new()),
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            _ => {}
+        }
+        if c != '|' || quote.is_some() {
+            parts.last_mut().unwrap().push(c);
+        }
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use test_case::test_case;
+    use crate::execution::ExecutionResult;
+    use crate::expression::Scope;
+    use crate::template::Template;
+
+    struct TestScope {
+        env: HashMap<String, String>,
+        tasks: HashMap<String, ExecutionResult>,
+    }
+
+    impl Scope for TestScope {
+        fn env(&self, name: &str) -> Option<String> {
+            self.env.get(name).cloned()
+        }
+
+        fn task(&self, id: &str) -> Option<&ExecutionResult> {
+            self.tasks.get(id)
+        }
+
+        fn builtin(&self, name: &str) -> Option<String> {
+            match name {
+                "job.id" => Some("backup".to_string()),
+                _ => None,
+            }
+        }
+    }
+
+    fn scope() -> TestScope {
+        let mut fetch = ExecutionResult::new("fetch".to_string(), None);
+        fetch.outputs.insert("FILE".to_string(), "data.json".to_string());
+        TestScope {
+            env: [("NAME", " World "), ("DIR", "/var/data")].iter()
+                .map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            tasks: [("fetch".to_string(), fetch)].into_iter().collect(),
+        }
+    }
+
+    #[test_case("no template", "no template")]
+    #[test_case("Hello ${{ NAME | trim }}!", "Hello World!")]
+    #[test_case("${{ env.NAME | trim | upper }}", "WORLD")]
+    #[test_case("${{ MISSING | default('fallback') }}", "fallback")]
+    #[test_case("${{ DIR | join('logs') }}", "/var/data/logs")]
+    #[test_case("${{ tasks.fetch.outputs.FILE }}", "data.json")]
+    #[test_case("${{ job.id }}-${{ 'literal|text' | upper }}", "backup-LITERAL|TEXT")]
+    fn test_render(source: &str, expected: &str) {
+        let template = Template::from_str(source).unwrap();
+        assert_eq!(template.render(&scope()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_render_unresolved() {
+        let template = Template::from_str("${{ MISSING | upper }}").unwrap();
+        let err = template.render(&scope()).unwrap_err();
+        assert!(err.to_string().contains("Unresolved variable \"MISSING\""));
+    }
+
+    #[test_case("${{ env.NAME }} ${{ tasks.fetch.outputs.FILE }} ${{ task.id }}", true, true)]
+    #[test_case("${{ job.name }}-${{ run.log_dir }}", false, true)]
+    #[test_case("${{ task.name }}", false, false)]
+    #[test_case("${{ tasks.fetch.outputs.FILE }}", false, false)]
+    #[test_case("${{ tasks.fetch.status }}", true, false)]
+    #[test_case("${{ run.unknown }}", true, false)]
+    #[test_case("${{ run.unknown | default('none') }}", true, true)]
+    fn test_check_variables(source: &str, in_task: bool, valid: bool) {
+        let template = Template::from_str(source).unwrap();
+        assert_eq!(template.check_variables(in_task).is_ok(), valid);
+    }
+
+    #[test_case("${{ NAME" ; "unterminated")]
+    #[test_case("${{ NAME | unknown }}" ; "unknown filter")]
+    #[test_case("${{ NAME | default }}" ; "missing argument")]
+    #[test_case("${{ }}" ; "empty expression")]
+    fn test_parse_invalid(source: &str) {
+        assert!(Template::from_str(source).is_err());
+    }
+}
@@ -1,8 +1,10 @@
 use rand::{self, Rng, SeedableRng};
 use rand::{distributions::Alphanumeric, rngs::SmallRng};
-use std::{cell::UnsafeCell, ffi::{OsStr, OsString}, io};
+use std::{borrow::Cow, cell::UnsafeCell, ffi::{OsStr, OsString}, io};
 use std::path::{Path, PathBuf};
 use anyhow::{Result};
+use lazy_static::lazy_static;
+use regex::Regex;
 
 thread_local! {
     static THREAD_RNG: UnsafeCell<SmallRng> = UnsafeCell::new(SmallRng::from_entropy());
@@ -21,7 +23,7 @@ fn tmpname(prefix: &OsStr, suffix: &OsStr, rand_len: usize) -> OsString {
         (&mut *rng.get())
             .sample_iter(&Alphanumeric)
             .take(rand_len)
-            .for_each(|b| buf.push(std::str::from_utf8_unchecked(&[b as u8])))
+            .for_each(|b| buf.push(std::str::from_utf8_unchecked(&[b])))
     });
     buf.push(suffix);
     buf
@@ -36,8 +38,8 @@ pub fn tmpfile(base: &Path, prefix: &str, suffix: &str) -> io::Result<PathBuf> {
 
 /// Returns cwd path relative to the current cwd.
 /// If the desired cwd is absolute, it is returned as is.
-pub fn resolve_cwd(current: &PathBuf, cwd: Option<&String>) -> PathBuf {
-    let cwd = cwd.map(PathBuf::from).unwrap_or_else(|| current.clone());
+pub fn resolve_cwd(current: &Path, cwd: Option<&String>) -> PathBuf {
+    let cwd = cwd.map(PathBuf::from).unwrap_or_else(|| current.to_path_buf());
     if cwd.is_absolute() {
         cwd
     } else {
@@ -45,10 +47,102 @@ pub fn resolve_cwd(current: &PathBuf, cwd: Option<&String>) -> PathBuf {
     }
 }
 
+/// Finds an executable by its name within the PATH.
+/// If a path is given instead of a name, it is checked whether it exists.
+pub fn find_executable(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(program))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// Returns the id a job gets from its file name
+pub fn job_id_from_path(path: &str) -> String {
+    PathBuf::from(path).file_stem().and_then(|f| f.to_str()).unwrap_or_default().to_string()
+}
+
+/// Whether the text matches a glob pattern, where `*` matches any sequence of characters
+/// and `?` matches a single character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = regex::escape(pattern).replace(r"\*", ".*").replace(r"\?", ".");
+    Regex::new(&format!("^{}$", pattern)).map(|regex| regex.is_match(text)).unwrap_or(false)
+}
+
+lazy_static! {
+    /// Matches the ANSI escape sequences (control sequences, operating system commands and single character escapes)
+    static ref ANSI_PATTERN: regex::bytes::Regex = regex::bytes::Regex::new(
+        r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(\x07|\x1b\\)|\x1b[@-Z\\-_]"
+    ).unwrap();
+}
+
+/// Removes the ANSI escape sequences (e.g. colors) from the text
+pub fn strip_ansi(text: &[u8]) -> Cow<'_, [u8]> {
+    ANSI_PATTERN.replace_all(text, &b""[..])
+}
+
+lazy_static! {
+    /// Matches the `${VAR}` references to environment variables and the escaped `$$`
+    static ref ENV_VAR_PATTERN: Regex = Regex::new(r"\$\$|\$\{([A-Za-z_][A-Za-z0-9_]*)}").unwrap();
+}
+
+/// Expands the `${VAR}` references within the text with the values of the environment variables.
+/// Undefined variables expand to an empty string and `$$` expands to a literal `$`.
+pub fn expand_env(text: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    ENV_VAR_PATTERN.replace_all(text, |captures: &regex::Captures| match captures.get(1) {
+        Some(name) => lookup(name.as_str()).unwrap_or_default(),
+        None => "$".to_string(),
+    }).to_string()
+}
+
+/// Replacement of the masked secret values
+pub const SECRET_MASK: &str = "***";
+
+/// Replaces all the occurrences of the secret values within the text with `***`.
+/// Longer secrets should come first, so that they are not partially masked by the shorter ones.
+pub fn mask_secrets<'a>(text: &'a [u8], secrets: &[String]) -> Cow<'a, [u8]> {
+    let mut result = Cow::Borrowed(text);
+    for secret in secrets.iter().map(String::as_bytes).filter(|secret| !secret.is_empty()) {
+        if !result.windows(secret.len()).any(|window| window == secret) {
+            continue;
+        }
+        let mut masked = Vec::with_capacity(result.len());
+        let mut rest = &result[..];
+        while !rest.is_empty() {
+            if rest.starts_with(secret) {
+                masked.extend_from_slice(SECRET_MASK.as_bytes());
+                rest = &rest[secret.len()..];
+            } else {
+                masked.push(rest[0]);
+                rest = &rest[1..];
+            }
+        }
+        result = Cow::Owned(masked);
+    }
+    result
+}
+
+/// Replaces all the occurrences of the secret values within the text with `***`
+pub fn mask_text(text: &str, secrets: &[String]) -> String {
+    String::from_utf8_lossy(&mask_secrets(text.as_bytes(), secrets)).to_string()
+}
+
+/// Returns a stable (FNV-1a) hash of the given contents as a hex string
+pub fn content_hash(contents: &str) -> String {
+    let hash = contents.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
 /// Executes a function with a reserved temporary file
 /// The temporary file is deleted when the function returns
 pub fn with_tempfile<R>(
-    base: &PathBuf,
+    base: &Path,
     f: impl FnOnce(&PathBuf) -> Result<R>,
 ) -> Result<R> {
     let temp_path = tmpfile(base, "nauman", "")?;
@@ -66,7 +160,8 @@ pub fn with_tempfile<R>(
 mod tests {
     use std::path::PathBuf;
     use anyhow::anyhow;
-    use crate::utils::{resolve_cwd, with_tempfile};
+    use test_case::test_case;
+    use crate::utils::{content_hash, expand_env, glob_match, mask_secrets, resolve_cwd, strip_ansi, with_tempfile};
 
     #[test]
     fn test_with_tempfile() {
@@ -107,4 +202,47 @@ mod tests {
         assert_eq!(resolve_cwd(&base,  None), PathBuf::from("/base"));
         assert_eq!(resolve_cwd(&base,  Some(&absolute)), PathBuf::from("/absolute"));
     }
-}
\ No newline at end of file
+
+    #[test_case("build", "build", true)]
+    #[test_case("build", "build-docs", false)]
+    #[test_case("build*", "build-docs", true)]
+    #[test_case("*_deploy", "003_deploy", true)]
+    #[test_case("00?_test", "002_test", true)]
+    #[test_case("0.1", "011", false)]
+    fn test_glob_match(pattern: &str, text: &str, expected: bool) {
+        assert_eq!(glob_match(pattern, text), expected);
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(content_hash(""), "cbf29ce484222325");
+        assert_eq!(content_hash("name: test"), content_hash("name: test"));
+        assert_ne!(content_hash("name: test"), content_hash("name: test2"));
+    }
+
+    #[test_case("\x1b[32mgreen\x1b[0m", "green" ; "colors")]
+    #[test_case("\x1b[1;31;40mbold\x1b[K", "bold" ; "multiple parameters")]
+    #[test_case("\x1b]0;title\x07text", "text" ; "operating system command")]
+    #[test_case("plain [text]", "plain [text]" ; "plain")]
+    fn test_strip_ansi(text: &str, expected: &str) {
+        assert_eq!(strip_ansi(text.as_bytes()), expected.as_bytes());
+    }
+
+    #[test_case("token=abc123", &["abc123"], "token=***" ; "single")]
+    #[test_case("abc123 and abc123", &["abc123"], "*** and ***" ; "repeated")]
+    #[test_case("abc123456", &["abc123456", "abc123"], "***" ; "longest first")]
+    #[test_case("user:pass", &["user", "pass"], "***:***" ; "multiple")]
+    #[test_case("nothing here", &["abc123", ""], "nothing here" ; "no match")]
+    fn test_mask_secrets(text: &str, secrets: &[&str], expected: &str) {
+        let secrets: Vec<String> = secrets.iter().map(|secret| secret.to_string()).collect();
+        assert_eq!(mask_secrets(text.as_bytes(), &secrets), expected.as_bytes());
+    }
+
+    #[test_case("${NAME}.txt", "my file.txt" ; "variable")]
+    #[test_case("$NAME ${MISSING}", "$NAME " ; "missing")]
+    #[test_case("$${NAME} $$", "${NAME} $" ; "escaped")]
+    fn test_expand_env(text: &str, expected: &str) {
+        let lookup = |name: &str| (name == "NAME").then(|| "my file".to_string());
+        assert_eq!(expand_env(text, lookup), expected);
+    }
+}
@@ -0,0 +1,518 @@
+use std::fmt::{Display, Formatter};
+use schemars::schema::{ObjectValidation, RootSchema, Schema, SchemaObject, SingleOrVec};
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::Marker;
+use crate::common::LocatedError;
+use crate::config::{self, TaskHandler};
+use crate::flow::Flow;
+use crate::http::Url;
+use crate::template::Template;
+use crate::utils::{find_executable, job_id_from_path, resolve_cwd};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    /// A problem which might not be an issue at the time the job is executed
+    Warning,
+}
+
+/// A problem found in a job file
+#[derive(Debug, Clone)]
+pub struct Problem {
+    /// Line and column of the problem within the job file (both starting at 1)
+    pub location: Option<(usize, usize)>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(location: Option<(usize, usize)>, message: impl ToString) -> Self {
+        Problem { location, severity: Severity::Error, message: message.to_string() }
+    }
+
+    fn warning(location: Option<(usize, usize)>, message: impl ToString) -> Self {
+        Problem { location, severity: Severity::Warning, message: message.to_string() }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some((line, column)) = self.location {
+            write!(f, "{}:{}: ", line, column)?;
+        }
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum NodeValue {
+    Scalar(String),
+    Sequence(Vec<Node>),
+    Mapping(Vec<(Node, Node)>),
+}
+
+/// Node of a yaml document including its location
+#[derive(Debug)]
+struct Node {
+    value: NodeValue,
+    location: (usize, usize),
+}
+
+impl Node {
+    fn new(value: NodeValue, marker: Marker) -> Self {
+        Node { value, location: (marker.line(), marker.col() + 1) }
+    }
+
+    /// Parses a yaml document keeping track of the node locations
+    fn parse(source: &str) -> Result<Option<Node>, Problem> {
+        let mut builder = NodeBuilder::default();
+        Parser::new(source.chars()).load(&mut builder, false).map_err(|e| Problem::new(
+            Some((e.marker().line(), e.marker().col() + 1)), format!("Invalid yaml: {}", e)
+        ))?;
+        Ok(builder.root)
+    }
+
+    fn as_str(&self) -> &str {
+        match &self.value {
+            NodeValue::Scalar(value) => value,
+            _ => "",
+        }
+    }
+
+    /// Finds the node at the given path (e.g. `tasks.2.needs`).
+    /// For mapping entries the key node is returned as it points to the start of the entry.
+    fn find(&self, path: &str) -> Option<&Node> {
+        let mut current = self;
+        let mut found = self;
+        for segment in path.split('.') {
+            match &current.value {
+                NodeValue::Mapping(entries) => {
+                    let (key, value) = entries.iter().find(|(key, _)| key.as_str() == segment)?;
+                    found = key;
+                    current = value;
+                }
+                NodeValue::Sequence(items) => {
+                    current = items.get(segment.parse::<usize>().ok()?)?;
+                    found = current;
+                }
+                NodeValue::Scalar(_) => return None,
+            }
+        }
+        Some(found)
+    }
+
+    /// Returns the location of the closest existing node to the given path
+    fn locate(&self, path: &str) -> Option<(usize, usize)> {
+        let mut path = path;
+        loop {
+            if let Some(node) = self.find(path) {
+                return Some(node.location);
+            }
+            path = &path[..path.rfind('.')?];
+        }
+    }
+}
+
+/// Node under construction together with the pending mapping key
+struct Frame {
+    node: Node,
+    key: Option<Node>,
+}
+
+/// Builds a node tree from the yaml parser events
+#[derive(Default)]
+struct NodeBuilder {
+    stack: Vec<Frame>,
+    root: Option<Node>,
+}
+
+impl NodeBuilder {
+    fn push_value(&mut self, node: Node) {
+        match self.stack.last_mut() {
+            None => self.root = Some(node),
+            Some(frame) => match &mut frame.node.value {
+                NodeValue::Sequence(items) => items.push(node),
+                NodeValue::Mapping(entries) => match frame.key.take() {
+                    Some(key) => entries.push((key, node)),
+                    None => frame.key = Some(node),
+                },
+                NodeValue::Scalar(_) => unreachable!(),
+            },
+        }
+    }
+}
+
+impl MarkedEventReceiver for NodeBuilder {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        match event {
+            Event::Scalar(value, ..) => self.push_value(Node::new(NodeValue::Scalar(value), marker)),
+            // Aliases are not expanded, since their content has already been checked at their anchor
+            Event::Alias(_) => self.push_value(Node::new(NodeValue::Scalar(String::new()), marker)),
+            Event::SequenceStart(_) => self.stack.push(Frame {
+                node: Node::new(NodeValue::Sequence(Vec::new()), marker), key: None,
+            }),
+            Event::MappingStart(_) => self.stack.push(Frame {
+                node: Node::new(NodeValue::Mapping(Vec::new()), marker), key: None,
+            }),
+            Event::SequenceEnd | Event::MappingEnd => {
+                let frame = self.stack.pop().expect("Unbalanced yaml events");
+                self.push_value(frame.node);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Checks the keys of a yaml document against the job schema
+struct SchemaChecker<'a> {
+    root: &'a RootSchema,
+}
+
+impl<'a> SchemaChecker<'a> {
+    /// Expands the references and subschemas into the list of schemas they are composed of
+    fn expand(&self, schema: &'a Schema, result: &mut Vec<&'a SchemaObject>) {
+        let object = match schema {
+            Schema::Object(object) => object,
+            Schema::Bool(_) => return,
+        };
+        if let Some(reference) = &object.reference {
+            let name = reference.trim_start_matches("#/definitions/");
+            if let Some(schema) = self.root.definitions.get(name) {
+                self.expand(schema, result);
+            }
+            return;
+        }
+
+        result.push(object);
+        if let Some(subschemas) = &object.subschemas {
+            let all = subschemas.all_of.iter().chain(&subschemas.any_of).chain(&subschemas.one_of);
+            for schema in all.flatten() {
+                self.expand(schema, result);
+            }
+        }
+    }
+
+    /// Returns the schema of the values of an object which accepts arbitrary keys
+    fn additional(object: &'a ObjectValidation) -> Option<Option<&'a Schema>> {
+        match object.additional_properties.as_deref() {
+            Some(Schema::Bool(false)) => None,
+            Some(schema) => Some(Some(schema)),
+            None if object.properties.is_empty() => Some(None),
+            None => None,
+        }
+    }
+
+    fn check(&self, node: &Node, schemas: &[&'a SchemaObject], path: &str, problems: &mut Vec<Problem>) {
+        match &node.value {
+            NodeValue::Mapping(entries) => {
+                let objects: Vec<&ObjectValidation> = schemas.iter()
+                    .filter_map(|schema| schema.object.as_deref())
+                    .collect();
+                if objects.is_empty() {
+                    return;
+                }
+
+                for (key, value) in entries {
+                    let name = key.as_str();
+                    let mut known = false;
+                    let mut children = Vec::new();
+                    for object in &objects {
+                        if let Some(schema) = object.properties.get(name) {
+                            known = true;
+                            self.expand(schema, &mut children);
+                        } else if let Some(schema) = Self::additional(object) {
+                            known = true;
+                            if let Some(schema) = schema {
+                                self.expand(schema, &mut children);
+                            }
+                        }
+                    }
+
+                    let child_path = if path.is_empty() { name.to_string() } else { format!("{}.{}", path, name) };
+                    if known {
+                        self.check(value, &children, &child_path, problems);
+                    } else if path.is_empty() {
+                        problems.push(Problem::new(Some(key.location), format!("Unknown key \"{}\"", name)));
+                    } else {
+                        problems.push(Problem::new(Some(key.location), format!("Unknown key \"{}\" in {}", name, path)));
+                    }
+                }
+            }
+            NodeValue::Sequence(items) => {
+                let mut children = Vec::new();
+                for schema in schemas {
+                    if let Some(SingleOrVec::Single(item)) = schema.array.as_ref().and_then(|array| array.items.as_ref()) {
+                        self.expand(item, &mut children);
+                    }
+                }
+                if children.is_empty() {
+                    return;
+                }
+                for (index, item) in items.iter().enumerate() {
+                    self.check(item, &children, &format!("{}.{}", path, index), problems);
+                }
+            }
+            NodeValue::Scalar(_) => {}
+        }
+    }
+}
+
+/// Visits all the tasks of a routine including the tasks of their hooks
+fn visit_tasks(tasks: &config::Tasks, path: &str, f: &mut impl FnMut(&config::Task, &str)) {
+    for (index, task) in tasks.iter().enumerate() {
+        let task_path = format!("{}.{}", path, index);
+        f(task, &task_path);
+        for (hook, tasks) in task.hooks.iter().flatten() {
+            visit_tasks(tasks, &format!("{}.hooks.{}", task_path, hook), f);
+        }
+    }
+}
+
+/// Checks whether the shells, directories and files referenced by the job exist
+fn check_environment(job: &config::Job, node: &Node, problems: &mut Vec<Problem>) {
+    let options = job.options.clone().unwrap_or_default();
+    let error = |path: &str, message: String| Problem::new(node.locate(path), message);
+
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    let job_cwd = resolve_cwd(&current_dir, job.cwd.as_ref());
+    let is_static = |path: &String| !Template::is_template(path);
+    if job.cwd.iter().any(is_static) && !job_cwd.is_dir() {
+        problems.push(error("cwd", format!("Working directory {:?} does not exist", job_cwd)));
+    }
+    if let Some(dotenv) = &options.dotenv {
+        if !dotenv.is_file() {
+            problems.push(error("options.dotenv", format!("Dotenv file {:?} does not exist", dotenv)));
+        }
+    }
+    let log_dir = resolve_cwd(&job_cwd, options.log_dir.as_ref());
+    if log_dir.exists() && !log_dir.is_dir() {
+        problems.push(error("options.log_dir", format!("Log directory {:?} is not a directory", log_dir)));
+    }
+
+    let mut check_task = |task: &config::Task, path: &str| {
+        // The directory might be created by one of the prior tasks
+        if let Some(cwd) = task.cwd.as_ref().filter(|cwd| is_static(cwd)) {
+            let cwd = resolve_cwd(&job_cwd, Some(cwd));
+            if !cwd.is_dir() {
+                let path = format!("{}.cwd", path);
+                problems.push(Problem::warning(
+                    node.locate(&path), format!("Working directory {:?} does not exist yet", cwd)
+                ));
+            }
+        }
+
+        let shell = match &task.handler {
+            Some(TaskHandler::Shell(handler)) => Some((&handler.shell, &handler.shell_path)),
+            Some(TaskHandler::Script(handler)) if handler.has_shell() => Some((&handler.shell, &handler.shell_path)),
+            _ => None,
+        };
+        if let Some((handler_shell, handler_shell_path)) = shell {
+            let shell = handler_shell.clone().unwrap_or_else(|| options.shell.clone());
+            let shell_path = handler_shell_path.as_ref().or_else(|| {
+                if shell == options.shell { options.shell_path.as_ref() } else { None }
+            });
+            let field = format!("{}.{}", path, if handler_shell_path.is_some() { "shell_path" } else { "shell" });
+            match shell.executable(shell_path) {
+                Ok(program) if find_executable(&program).is_none() => problems.push(
+                    error(&field, format!("Shell executable \"{}\" could not be found", program))
+                ),
+                Ok(_) => {}
+                Err(e) => problems.push(error(&field, e.to_string())),
+            }
+        }
+
+        if let Some(TaskHandler::Exec(handler)) = &task.handler {
+            let field = format!("{}.command", path);
+            match handler.command.first() {
+                None => problems.push(error(&field, "Command must contain at least the program".to_string())),
+                // Programs given as a path are resolved relative to the task cwd
+                Some(program) if is_static(program) && !program.contains('$') && !program.contains('/') => {
+                    if find_executable(program).is_none() {
+                        problems.push(error(&field, format!("Program \"{}\" could not be found", program)));
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some(TaskHandler::Http(handler)) = &task.handler {
+            let request = &handler.http;
+            if is_static(&request.url) {
+                if let Err(e) = Url::parse(&request.url) {
+                    problems.push(error(&format!("{}.http.url", path), e.to_string()));
+                }
+            }
+            if request.body.is_some() && request.json.is_some() {
+                problems.push(error(&format!("{}.http.json", path), "Only one of \"body\" and \"json\" can be set".to_string()));
+            }
+        }
+    };
+    visit_tasks(&job.tasks, "tasks", &mut check_task);
+    for (hook, tasks) in job.hooks.iter().flatten() {
+        visit_tasks(tasks, &format!("hooks.{}", hook), &mut check_task);
+    }
+    for (name, template) in job.templates.iter().flatten() {
+        check_task(template, &format!("templates.{}", name));
+    }
+}
+
+/// Validates a job file without executing it and returns all the problems found.
+/// The job gets its id from the path, which also locates the included files.
+pub fn validate_job(source: &str, job_path: &str) -> Vec<Problem> {
+    let node = match Node::parse(source) {
+        Ok(Some(node)) => node,
+        Ok(None) => return vec![Problem::new(None, "Job file is empty")],
+        Err(problem) => return vec![problem],
+    };
+
+    // Check for unknown keys, which are ignored when the job is executed
+    let mut problems = Vec::new();
+    let schema = config::Job::schema();
+    let root = Schema::Object(schema.schema.clone());
+    let checker = SchemaChecker { root: &schema };
+    let mut schemas = Vec::new();
+    checker.expand(&root, &mut schemas);
+    checker.check(&node, &schemas, "", &mut problems);
+
+    let mut job: config::Job = match serde_yaml::from_str(source) {
+        Ok(job) => job,
+        Err(e) => {
+            let location = e.location().map(|location| (location.line(), location.column()));
+            // The location is already reported separately
+            let message = e.to_string();
+            let message = match location {
+                Some((line, column)) => message.trim_end_matches(&format!(" at line {} column {}", line, column)),
+                None => &message,
+            };
+            problems.push(Problem::new(location, message));
+            return problems;
+        }
+    };
+    job.id = Some(job.id.unwrap_or_else(|| job_id_from_path(job_path)));
+    job.path = std::fs::canonicalize(job_path).ok();
+
+    if let Err(errors) = Flow::parse_all(&job) {
+        for e in errors {
+            let location = e.downcast_ref::<LocatedError>().and_then(|e| node.locate(&e.path));
+            problems.push(Problem::new(location, e));
+        }
+    }
+    check_environment(&job, &node, &mut problems);
+
+    problems.sort_by_key(|problem| problem.location);
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validate::validate_job;
+
+    #[test]
+    fn test_valid_job() {
+        let problems = validate_job(r#"
+name: test
+tasks:
+  - id: a
+    run: "true"
+    retry:
+      attempts: 2
+logging:
+  - type: file
+    output: ./test.log
+"#, "test");
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let problems = validate_job(r#"
+name: test
+unknown: true
+tasks:
+  - id: a
+    run: "true"
+    retyr:
+      attempts: 2
+    env:
+      ANY_NAME: value
+"#, "test");
+        let messages: Vec<String> = problems.iter().map(|problem| problem.to_string()).collect();
+        assert_eq!(messages, vec![
+            "3:1: error: Unknown key \"unknown\"".to_string(),
+            "7:5: error: Unknown key \"retyr\" in tasks.0".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_flow_error_location() {
+        let problems = validate_job(r#"
+name: test
+tasks:
+  - id: a
+    run: "true"
+  - id: b
+    run: "true"
+    needs: [c]
+"#, "test");
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].location, Some((8, 5)));
+        assert!(problems[0].message.contains("unknown task \"c\""));
+    }
+
+    #[test]
+    fn test_all_flow_errors() {
+        let problems = validate_job(r#"
+name: test
+tasks:
+  - id: a
+    run: "true"
+    if: "=="
+  - id: b
+    extends: missing
+  - id: c
+    name: Print
+    run: echo ${{ run.unknown }}
+  - id: a
+    run: "true"
+"#, "test");
+        let locations: Vec<Option<(usize, usize)>> = problems.iter().map(|problem| problem.location).collect();
+        assert_eq!(locations, vec![Some((6, 5)), Some((8, 5)), Some((11, 5)), Some((12, 5))], "{:?}", problems);
+        assert!(problems[0].message.contains("invalid condition"));
+        assert!(problems[1].message.contains("unknown template \"missing\""));
+        assert!(problems[2].message.contains("Unknown variable \"run.unknown\""));
+        assert!(problems[3].message.contains("duplicate id"));
+    }
+
+    #[test]
+    fn test_exec_command() {
+        let problems = validate_job(r#"
+name: test
+tasks:
+  - id: a
+    command: []
+  - id: b
+    command: [nauman-missing-program, "${FILE}"]
+  - id: c
+    command: [echo, "${FILE}"]
+"#, "test");
+        let messages: Vec<String> = problems.iter().map(|problem| problem.to_string()).collect();
+        assert_eq!(messages, vec![
+            "5:5: error: Command must contain at least the program".to_string(),
+            "7:5: error: Program \"nauman-missing-program\" could not be found".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_yaml() {
+        let problems = validate_job("name: test\ntasks: [\n", "test");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].location.is_some());
+    }
+}